@@ -22,6 +22,9 @@ use std::process::Command;
 fn main() {
     let pretend_debug = env::var("BUILDRS_PRETEND_DEBUG").unwrap_or_default() == "1";
 
+    // let rustc know about `build` so #[cfg(build = ..)] isnt an unexpected cfg
+    println!("cargo:rustc-check-cfg=cfg(build, values(\"release\", \"debug\"))");
+
     if let Ok(mut profile) = env::var("PROFILE") {
         if pretend_debug {
             profile = "debug".to_string();
@@ -0,0 +1,65 @@
+//! Headless authoritative server, run the game with `--connect <addr>` to play on it.
+//!
+//! `cargo run --bin server -- [--bind <addr>] [--tps <tps>]`
+
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use interp_test::net::{Server, DEFAULT_PORT};
+use interp_test::state::Simulation;
+use interp_test::world::World;
+use macroquad::math::Vec2;
+
+/// Middle of the default window.
+const SPAWN: Vec2 = Vec2::new(400.0, 300.0);
+
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    let arg = |name: &str| args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1));
+
+    let default_addr = format!("0.0.0.0:{}", DEFAULT_PORT);
+    let addr = arg("--bind").unwrap_or(&default_addr);
+    let tps = match arg("--tps").map(|tps| tps.parse::<f32>()) {
+        None => 30.0,
+        Some(Ok(tps)) => tps,
+        Some(Err(err)) => {
+            eprintln!("Bad tps: {}", err);
+            return;
+        }
+    };
+
+    let mut sim = match Simulation::new(tps, SPAWN) {
+        Ok(sim) => sim,
+        Err(err) => {
+            eprintln!("Bad tps: {}", err);
+            return;
+        }
+    };
+    sim.set_world(World::arena());
+    let mut server = match Server::bind(addr, sim) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("Failed to bind {}: {}", addr, err);
+            return;
+        }
+    };
+    println!("Listening on {} at {} tps", addr, tps);
+
+    let mut last = Instant::now();
+    loop {
+        let now = Instant::now();
+        let delta_time = (now - last).as_secs_f32();
+        last = now;
+
+        let had_client = server.client.is_some();
+        if let Err(err) = server.update(delta_time) {
+            eprintln!("Network error: {}", err);
+        }
+        if let (false, Some(client)) = (had_client, server.client) {
+            println!("Client connected from {}", client);
+        }
+
+        // input is polled this often, well under a tick
+        sleep(Duration::from_millis(1));
+    }
+}
@@ -0,0 +1,269 @@
+//! Settings that survive restarts, in a small TOML file.
+//!
+//! Only `key = value` lines and `#` comments, which is all the settings need, so no toml
+//! crate. Every key can also be set from the command line, like `--tps 60` or
+//! `--avg-strategy "DecayingMean(0.05)"`.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+use crate::player::AveragingStrategy;
+use crate::replay::invalid;
+use crate::state::{GameState, GlobalState, TickSettings, TickSettingsError};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub tps: f32,
+    pub buffer_secs: f32,
+    pub timescale: f32,
+    pub avg_strategy: AveragingStrategy,
+    pub dont_interpolate: bool,
+    pub dbg_buffer: bool,
+    pub dbg_hide_interp_info: bool,
+}
+
+impl Config {
+    pub const PATH: &str = "settings.toml";
+    pub const DEFAULT_TPS: f32 = 30.0;
+    pub const KEYS: [&str; 7] = [
+        "tps",
+        "buffer_secs",
+        "timescale",
+        "avg_strategy",
+        "dont_interpolate",
+        "dbg_buffer",
+        "dbg_hide_interp_info",
+    ];
+
+    pub fn from_global(global_state: &GlobalState) -> Self {
+        let tick_settings = &global_state.tick_settings;
+        Self {
+            tps: tick_settings.tps,
+            buffer_secs: tick_settings.buffer_secs,
+            timescale: tick_settings.timescale(),
+            avg_strategy: global_state.avg_strategy,
+            dont_interpolate: global_state.dont_interpolate,
+            dbg_buffer: global_state.dbg_buffer,
+            dbg_hide_interp_info: global_state.dbg_hide_interp_info,
+        }
+    }
+
+    /// Fails if [`TickSettings::is_sane`] wouldn't be true.
+    pub fn tick_settings(&self) -> Result<TickSettings, TickSettingsError> {
+        let mut tick_settings = TickSettings::new(self.tps)?;
+        tick_settings.set_buffer_secs(self.buffer_secs)?;
+        tick_settings.set_timescale(self.timescale);
+        Ok(tick_settings)
+    }
+
+    /// Changes nothing if the tick settings aren't sane.
+    pub fn apply(
+        &self, game_state: &mut GameState, global_state: &mut GlobalState,
+    ) -> Result<(), TickSettingsError> {
+        global_state.set_tick_settings(game_state, self.tick_settings()?);
+        global_state.avg_strategy = self.avg_strategy;
+        global_state.dont_interpolate = self.dont_interpolate;
+        global_state.dbg_buffer = self.dbg_buffer;
+        global_state.dbg_hide_interp_info = self.dbg_hide_interp_info;
+        Ok(())
+    }
+
+    /// Set one of [`Config::KEYS`] from how it's written in the file.
+    pub fn set(&mut self, key: &str, value: &str) -> io::Result<()> {
+        let value = value.trim().trim_matches('"');
+        let bad_value = || invalid(&format!("bad value for {}: {}", key, value));
+        let float = || value.parse::<f32>().map_err(|_| bad_value());
+        let bool = || value.parse::<bool>().map_err(|_| bad_value());
+        match key {
+            "tps" => self.tps = float()?,
+            "buffer_secs" => self.buffer_secs = float()?,
+            "timescale" => {
+                let timescale = float()?;
+                if !(timescale.is_normal() && timescale > 0.0) {
+                    return Err(bad_value());
+                }
+                self.timescale = timescale;
+            }
+            "avg_strategy" => {
+                self.avg_strategy = parse_avg_strategy(value).ok_or_else(bad_value)?
+            }
+            "dont_interpolate" => self.dont_interpolate = bool()?,
+            "dbg_buffer" => self.dbg_buffer = bool()?,
+            "dbg_hide_interp_info" => self.dbg_hide_interp_info = bool()?,
+            _ => return Err(invalid(&format!("unknown setting: {}", key))),
+        }
+        Ok(())
+    }
+
+    /// Override settings with `--key value` pairs, dashes or underscores both work.
+    /// Other arguments are skipped. Changes nothing on errors.
+    pub fn set_from_args(&mut self, args: &[String]) -> io::Result<()> {
+        let mut new = self.clone();
+        for pair in args.windows(2) {
+            let Some(key) = pair[0].strip_prefix("--") else {
+                continue;
+            };
+            let key = key.replace('-', "_");
+            if Self::KEYS.contains(&key.as_str()) {
+                new.set(&key, &pair[1])?;
+            }
+        }
+        new.tick_settings().map_err(|err| invalid(&err.to_string()))?;
+        *self = new;
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write(&mut File::create(path)?)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "# saved on exit, delete to go back to defaults")?;
+        writeln!(w, "tps = {}", self.tps)?;
+        writeln!(w, "buffer_secs = {}", self.buffer_secs)?;
+        writeln!(w, "timescale = {}", self.timescale)?;
+        writeln!(w, "avg_strategy = \"{:?}\"", self.avg_strategy)?;
+        writeln!(w, "dont_interpolate = {}", self.dont_interpolate)?;
+        writeln!(w, "dbg_buffer = {}", self.dbg_buffer)?;
+        writeln!(w, "dbg_hide_interp_info = {}", self.dbg_hide_interp_info)
+    }
+
+    /// Missing keys keep their default.
+    pub fn read(r: &mut impl BufRead) -> io::Result<Self> {
+        let mut config = Self::default();
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(invalid(&format!("line {}: expected key = value", i + 1)));
+            };
+            config
+                .set(key.trim(), value)
+                .map_err(|err| invalid(&format!("line {}: {}", i + 1, err)))?;
+        }
+        config.tick_settings().map_err(|err| invalid(&err.to_string()))?;
+        Ok(config)
+    }
+
+    /// Like [`Config::read`] but from a whole string.
+    pub fn parse(s: &str) -> io::Result<Self> {
+        Self::read(&mut s.as_bytes())
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        // NOTE:PANIC: the default tps is sane
+        Self::from_global(&GlobalState::new(Self::DEFAULT_TPS).unwrap())
+    }
+}
+
+/// Reads back the `Debug` output, like `Mean` or `DecayingMean(0.05)`.
+fn parse_avg_strategy(s: &str) -> Option<AveragingStrategy> {
+    let (name, arg) = match s.split_once('(') {
+        Some((name, rest)) => (name, Some(rest.strip_suffix(')')?)),
+        None => (s, None),
+    };
+    let strategy = match (name, arg) {
+        ("Oldest", None) => AveragingStrategy::Oldest,
+        ("Newest", None) => AveragingStrategy::Newest,
+        ("Mean", None) => AveragingStrategy::Mean,
+        ("MeanIgnoreZero", None) => AveragingStrategy::MeanIgnoreZero,
+        ("MeanNormalized", None) => AveragingStrategy::MeanNormalized,
+        ("MeanNormalizedPercent", Some(arg)) => {
+            AveragingStrategy::MeanNormalizedPercent(arg.parse().ok()?)
+        }
+        ("MeanIgnoreFirstXZeros", Some(arg)) => {
+            AveragingStrategy::MeanIgnoreFirstXZeros(arg.parse().ok()?)
+        }
+        ("TimeWeightedMean", None) => AveragingStrategy::TimeWeightedMean,
+        ("DecayingMean", Some(arg)) => AveragingStrategy::DecayingMean(arg.parse().ok()?),
+        _ => return None,
+    };
+    Some(strategy)
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::Vec2;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let config = Config {
+            tps: 45.0,
+            buffer_secs: 0.5,
+            timescale: 0.8,
+            avg_strategy: AveragingStrategy::DecayingMean(0.05),
+            dont_interpolate: true,
+            dbg_buffer: true,
+            dbg_hide_interp_info: false,
+        };
+        let mut file = Vec::new();
+        config.write(&mut file).unwrap();
+        assert_eq!(Config::read(&mut file.as_slice()).unwrap(), config);
+
+        let mut game = GameState::new(2, Vec2::ZERO);
+        let mut global = GlobalState::default();
+        config.apply(&mut game, &mut global).unwrap();
+        assert_eq!(game.buffer().len(), global.tick_settings.buffer_len);
+        assert_eq!(Config::from_global(&global), config);
+    }
+
+    #[test]
+    fn every_avg_strategy() {
+        use AveragingStrategy::*;
+
+        let all = [
+            Oldest,
+            Newest,
+            Mean,
+            MeanIgnoreZero,
+            MeanNormalized,
+            MeanNormalizedPercent(0.25),
+            MeanIgnoreFirstXZeros(3),
+            TimeWeightedMean,
+            DecayingMean(0.05),
+        ];
+        for strategy in all {
+            // no wildcard, a new variant won't compile until it's added above
+            match strategy {
+                Oldest | Newest | Mean | MeanIgnoreZero | MeanNormalized | TimeWeightedMean => {}
+                MeanNormalizedPercent(_) | MeanIgnoreFirstXZeros(_) | DecayingMean(_) => {}
+            }
+            let config = Config { avg_strategy: strategy, ..Default::default() };
+            let mut file = Vec::new();
+            config.write(&mut file).unwrap();
+            assert_eq!(Config::read(&mut file.as_slice()).unwrap(), config, "{:?}", strategy);
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let config = Config::parse("# comment\n\ntps = 60 # trailing\n").unwrap();
+        assert_eq!(config, Config { tps: 60.0, ..Default::default() });
+        assert!(Config::parse("tps = 5").is_err());
+        assert!(Config::parse("tps = fast").is_err());
+        assert!(Config::parse("timescale = 0").is_err());
+        assert!(Config::parse("avg_strategy = \"DecayingMean\"").is_err());
+        let err = Config::parse("\nfps = 60").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: unknown setting: fps");
+
+        let args = ["game", "--connect", "localhost", "--tps", "90", "--dbg-buffer", "true"];
+        let mut config = Config::default();
+        config.set_from_args(&args.map(String::from)).unwrap();
+        assert_eq!(config, Config { tps: 90.0, dbg_buffer: true, ..Default::default() });
+        let before = config.clone();
+        assert!(config.set_from_args(&["--buffer-secs".into(), "0.01".into()]).is_err());
+        assert_eq!(config, before);
+    }
+}
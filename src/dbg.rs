@@ -1,8 +1,11 @@
+use interp_test::entity::EntityKind;
 use interp_test::state::{GameState, GlobalState, TickSettings};
 use macroquad::prelude::*;
 use std::fmt::{Display, Error as FmtError, Write};
 
 const TYPEFACE_SIZE: f32 = 15.0;
+/// How long a refused settings change stays on screen.
+const REFUSED_SECS: f64 = 2.0;
 use const_format::formatcp;
 
 // type Writer<'a> = &'a mut dyn Write;
@@ -85,7 +88,7 @@ fn dbg_fps(w: &mut dyn Write) -> Result<(), FmtError> {
 fn dbg_player_pos(
     w: &mut dyn Write, game: &GameState, _global_state: &GlobalState,
 ) -> Result<(), FmtError> {
-    let player = &game.current_tick().player;
+    let player = &game.current_tick().player();
     let mov = &player.movement;
     let xy = BetterVec2Display(mov.pos);
     let speed = mov.vel.length();
@@ -99,10 +102,54 @@ fn dbg_player_pos(
     )
 }
 
+fn dbg_entities(
+    w: &mut dyn Write, game: &GameState, _global_state: &GlobalState,
+) -> Result<(), FmtError> {
+    let entities = &game.current_tick().entities;
+    write!(w, "entities: {}, next id: {}", entities.len(), entities.next_id())?;
+    for (id, entity) in entities.iter().filter(|(_, entity)| entity.kind != EntityKind::Player) {
+        write!(w, ", {} {} {:.0}", entity.kind, id, BetterVec2Display(entity.movement.pos))?;
+    }
+    Ok(())
+}
+
 fn dbg_player_line2(
     w: &mut dyn Write, _game: &GameState, global_state: &GlobalState,
 ) -> Result<(), FmtError> {
-    write!(w, "Input averaging: {}", global_state.avg_strategy)
+    write!(
+        w,
+        "Input averaging: {}, interpolation: {}",
+        global_state.avg_strategy, global_state.interp_strategy
+    )
+}
+
+fn dbg_extrapolation(
+    w: &mut dyn Write, _game: &GameState, global_state: &GlobalState,
+) -> Result<(), FmtError> {
+    let extrapolator = &global_state.extrapolator;
+    write!(
+        w,
+        "Updates paused: {}, missed ticks: {}, extrapolation: {} (max {}s), extrapolated ticks: {:.3}",
+        global_state.pause_updates,
+        global_state.missed_ticks,
+        extrapolator.enabled,
+        extrapolator.max_secs,
+        extrapolator.extrapolated_ticks
+    )
+}
+
+fn dbg_tick_control(
+    w: &mut dyn Write, game: &GameState, global_state: &GlobalState,
+) -> Result<(), FmtError> {
+    let control = &global_state.tick_control;
+    let (back, _) = game.view_back(control.view_back);
+    write!(
+        w,
+        "Paused: {}, viewing {:.2} ticks back, tick {}",
+        control.paused,
+        control.view_back,
+        game.tick_number.saturating_sub(back)
+    )
 }
 
 fn dbg_timings(
@@ -225,15 +272,22 @@ pub fn dbg_info(game: &GameState, global_state: &GlobalState, _t: f32) {
     let dbg_tick = |w: &mut dyn Write| {
         let tick = game.tick_number;
         let (gametime_s, gametime_ticks) = game.gametime_passed(tps);
-        write!(w, "tick: {:06}, gametime: {}+{:02}", tick, DaysHms::new(gametime_s), gametime_ticks)
+        let acc = &global_state.update_accumulator;
+        write!(w, "tick: {:06}, gametime: {}+{:02}, ticks this frame: {}, dropped: {}", tick, DaysHms::new(gametime_s), gametime_ticks, acc.last_ticks, acc.dropped_ticks)
     };
     draw_text(&to_string(dbg_tick), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
 
     // net stuff
     draw_text(&format!("set: tps: {}, tick time: {}s", tps, tick_len_secs), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
-    draw_text(&format!(" buffer: len: {}, time: {}s, actual time: {}s", buffer_len, buffer_secs, tick_len_secs * *buffer_len as f32), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
+    draw_text(&format!(" buffer: len: {}, capacity: {}, time: {}s, actual time: {}s", buffer_len, game.buffer().capacity(), buffer_secs, tick_len_secs * *buffer_len as f32), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
+    draw_text(&format!(" interp delay: {} + 1 ticks, {:.1}ms extra, cursor: {:.3}", global_state.interp_delay, global_state.interp_delay_secs() * 1000.0, global_state.cursor_progress()), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
     let time_scale = tick_settings.timescale();
     draw_text(&format!(" timescale: {}, speed factor: {}, reference tps: {}", time_scale, speed_factor, TickSettings::REFERENCE_TPS), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
+    if let Some((reason, since)) = &global_state.dbg_refused {
+        if get_time() - since < REFUSED_SECS {
+            draw_text(&format!(" refused: {}", reason), 0.0, next_line(), TYPEFACE_SIZE, RED);
+        }
+    }
 
     // draw_text(&format!(""), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
     // draw_text(&format!("version: {}", env!("CARGO_PKG_VERSION")), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
@@ -241,19 +295,36 @@ pub fn dbg_info(game: &GameState, global_state: &GlobalState, _t: f32) {
 
     draw_text(&to_string2(dbg_player_pos, game, global_state), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
     draw_text(&to_string2(dbg_player_line2, game, global_state), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
+    draw_text(&to_string2(dbg_entities, game, global_state), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
+    draw_text(&to_string2(dbg_extrapolation, game, global_state), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
+    draw_text(&to_string2(dbg_tick_control, game, global_state), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
     draw_text(&to_string2(dbg_timings, game, global_state), 0.0, next_line(), TYPEFACE_SIZE, WHITE);
 
     let controls = r#"Controls:
 - wasd, arrows to move
+- left click shoot towards the mouse
 - space teleport to middle
-- r reset settings
+- r reset settings to the ones at launch
 - f1, f2 change timescale
 - f3, f4 change tps
 - f5, f6 change tick buffer length
+- m free memory left over from shrinking buffers
+- f7, f8 change interpolation delay
 - u toggle tick visualization
 - i toggle interpolation
 - o toggle tick buffer visualization
-- p change input averaging method"#;
+- p change input averaging method
+- y change interpolation method
+- x pause updates
+- e toggle extrapolation
+- b pause, . , step a tick forward/back, hold shift to scrub
+- j continue from the viewed tick, throwing away newer ticks
+- k start/stop recording, saves to a file, not with --threaded
+- l replay last recording, or launch with --replay <file>
+- g toggle prediction when launched with --connect <addr>
+- n cycle pretend network conditions when connected
+- launch with --threaded to tick on its own thread
+- settings are saved to settings.toml on exit, launch with --tps 60 etc to override"#;
 
     y += TYPEFACE_SIZE;
     dbg_menu_print_string(controls, &mut y)
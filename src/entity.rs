@@ -0,0 +1,374 @@
+use std::fmt::Display;
+use std::io::{self, Read, Write};
+
+use macroquad::prelude::*;
+
+use crate::movement::{InterpolationStrategy, Movement};
+use crate::player::Player;
+use crate::replay::{invalid, read_bytes, read_varint, write_varint};
+use crate::state::{Fire, TickSettings};
+use crate::world::World;
+use crate::{DBG_NOW, DBG_PREV};
+
+/// Stays the same for as long as the entity exists, and isn't reused after it's gone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct EntityId(pub u32);
+
+impl EntityId {
+    /// The local player, always spawned first.
+    pub const PLAYER: Self = Self(0);
+}
+
+impl Display for EntityId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum EntityKind {
+    /// Moved by input.
+    #[default]
+    Player,
+    /// Moves on its own, circles around spawn.
+    Npc,
+    /// Flies straight until `ticks_left` runs out.
+    Projectile {
+        ticks_left: u16,
+        /// How far into the tick before it spawned it was fired, in 256ths.
+        subtick: u8,
+    },
+}
+
+impl EntityKind {
+    pub fn radius(&self) -> f32 {
+        match self {
+            EntityKind::Player => Player::PLAYER_SIZE,
+            EntityKind::Npc => Player::PLAYER_SIZE,
+            EntityKind::Projectile { .. } => Entity::PROJECTILE_SIZE,
+        }
+    }
+
+    /// Projectiles fly through each other.
+    pub fn is_hittable(&self) -> bool {
+        !matches!(self, EntityKind::Projectile { .. })
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            EntityKind::Player => BLACK,
+            EntityKind::Npc => DARKPURPLE,
+            EntityKind::Projectile { .. } => ORANGE,
+        }
+    }
+}
+
+impl Display for EntityKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EntityKind::Player => write!(f, "Player"),
+            EntityKind::Npc => write!(f, "Npc"),
+            EntityKind::Projectile { ticks_left, .. } => write!(f, "Projectile ({})", ticks_left),
+        }
+    }
+}
+
+/// Anything that moves around in a tick.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub movement: Movement,
+}
+
+impl Entity {
+    /// How far from spawn NPCs circle.
+    const NPC_ORBIT: f32 = 150.0;
+    pub const PROJECTILE_SIZE: f32 = 4.0;
+    /// Pixels per tick at the reference tps.
+    const PROJECTILE_SPEED: f32 = 40.0;
+    const PROJECTILE_LIFETIME_SECS: f32 = 1.0;
+
+    pub fn new(kind: EntityKind, pos: Vec2) -> Self {
+        Self { kind, movement: Movement { pos, ..Default::default() } }
+    }
+
+    /// A projectile fired by something that moved from `from` to `to` during the tick.
+    /// It starts where the shooter was when `fire` happened and already flew for the rest
+    /// of the tick.
+    pub fn projectile(from: Vec2, to: Vec2, fire: &Fire, tick_settings: &TickSettings) -> Self {
+        let fraction = fire.fraction();
+        let shooter = from.lerp(to, fraction);
+        let dir = (fire.target - shooter).try_normalize().unwrap_or(Vec2::X);
+        let vel = dir * Self::PROJECTILE_SPEED * tick_settings.speed_factor;
+        let muzzle = shooter + dir * (Player::PLAYER_SIZE + Self::PROJECTILE_SIZE);
+        let ticks_left = (Self::PROJECTILE_LIFETIME_SECS * tick_settings.tps).ceil() as u16;
+        Self {
+            kind: EntityKind::Projectile { ticks_left: ticks_left.max(1), subtick: fire.subtick },
+            movement: Movement { pos: muzzle + vel * (1.0 - fraction), vel, accel: Vec2::ZERO },
+        }
+    }
+
+    /// Move a projectile by `delta`, stopping at the first wall. It despawns the tick after.
+    pub fn fly(&mut self, world: &World, delta: Vec2) {
+        let radius = self.radius();
+        let EntityKind::Projectile { ticks_left, .. } = &mut self.kind else {
+            return;
+        };
+        match world.sweep(self.movement.pos, delta, radius) {
+            Some(contact) => {
+                self.movement.pos += delta * contact.t;
+                self.movement.vel = Vec2::ZERO;
+                *ticks_left = (*ticks_left).min(1);
+            }
+            None => self.movement.pos += delta,
+        }
+    }
+
+    /// Where to draw it between the tick before it spawned and the tick it spawned in,
+    /// `t` like for interpolating. `None` if it wasn't there yet at `t`.
+    pub fn spawn_pos(&self, t: f32) -> Option<Vec2> {
+        match self.kind {
+            EntityKind::Projectile { subtick, .. } => {
+                let fraction = subtick as f32 / 256.0;
+                (t >= fraction).then(|| self.movement.pos - self.movement.vel * (1.0 - t))
+            }
+            _ => Some(self.movement.pos),
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.kind.radius()
+    }
+
+    pub fn handle_movement(&mut self, wish_dir: Vec2, accel: f32) -> &mut Self {
+        self.movement.accel = wish_dir * accel;
+        self
+    }
+
+    /// Where an NPC wants to go: around `spawn`, towards the orbit if it's off it.
+    pub fn npc_wish_dir(&self, spawn: Vec2) -> Vec2 {
+        let offset = self.movement.pos - spawn;
+        let Some(out) = offset.try_normalize() else {
+            return Vec2::X;
+        };
+        // steer back hard, going around at full speed drifts outwards
+        let towards_orbit = (Self::NPC_ORBIT - offset.length()) / Self::NPC_ORBIT * 4.0;
+        (out.perp() + out * towards_orbit).normalize_or_zero()
+    }
+
+    /// Where the entity will be `ticks` in the future if it keeps accelerating the same way.
+    pub fn extrapolate(
+        &self, ticks: f32, speed_factor: f32, strategy: InterpolationStrategy,
+    ) -> Vec2 {
+        self.movement.extrapolate(
+            ticks,
+            Player::max_speed(speed_factor),
+            Player::base_friction(speed_factor),
+            Player::scaling_friction(speed_factor),
+            strategy,
+        )
+    }
+
+    /// Where it is `t` of the way from `prev`, without curving through `world`.
+    /// `before` is the tick before `prev`, used by some interpolation strategies.
+    pub fn interp(
+        &self, prev: &Self, before: Option<&Self>, t: f32, strategy: InterpolationStrategy,
+        world: &World,
+    ) -> Vec2 {
+        let before = before.map(|before| &before.movement);
+        let pos = prev.movement.interp_before(&self.movement, before, t, strategy);
+        world.constrain(prev.movement.pos, pos, self.radius())
+    }
+
+    pub fn draw(
+        &self, prev: &Self, before: Option<&Self>, t: f32, strategy: InterpolationStrategy,
+        world: &World,
+    ) -> &Self {
+        self.draw_at(self.interp(prev, before, t, strategy, world))
+    }
+
+    /// Draw at `pos` instead of interpolating.
+    pub fn draw_at(&self, pos: Vec2) -> &Self {
+        draw_circle(pos.x, pos.y, self.radius(), self.kind.color());
+        self
+    }
+
+    pub fn draw_dbg(&self, prev: &Self, _t: f32) -> &Self {
+        let Vec2 { x, y } = prev.movement.interp0(&self.movement, 1.0);
+        draw_circle(x, y, self.radius(), DBG_NOW);
+        self
+    }
+
+    pub fn draw_dbg_prev(&self, prev: &Self, _t: f32) -> &Self {
+        let Vec2 { x, y } = prev.movement.interp0(&self.movement, 0.0);
+        draw_circle(x, y, self.radius(), DBG_PREV);
+        self
+    }
+}
+
+/// Every entity in a tick, sorted by id.
+///
+/// Every tick has its own copy, so an entity exists in exactly the ticks between its
+/// spawn and despawn. Only interpolate between ticks that both have it.
+#[derive(Debug, PartialEq, Default)]
+pub struct Entities {
+    entities: Vec<(EntityId, Entity)>,
+    next_id: u32,
+}
+
+// by hand so clone_from reuses the Vec, ticks get copied into old ones every tick
+impl Clone for Entities {
+    fn clone(&self) -> Self {
+        Self { entities: self.entities.clone(), next_id: self.next_id }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.entities.clone_from(&source.entities);
+        self.next_id = source.next_id;
+    }
+}
+
+impl Entities {
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Id the next spawned entity gets.
+    pub fn next_id(&self) -> EntityId {
+        EntityId(self.next_id)
+    }
+
+    fn search(&self, id: EntityId) -> Result<usize, usize> {
+        self.entities.binary_search_by_key(&id, |(id, _)| *id)
+    }
+
+    pub fn spawn(&mut self, entity: Entity) -> EntityId {
+        let id = self.next_id();
+        self.next_id += 1;
+        // newest id is always the biggest
+        self.entities.push((id, entity));
+        id
+    }
+
+    /// Never despawns the player, so [`TickState::player`](crate::state::TickState::player)
+    /// can count on it.
+    pub fn despawn(&mut self, id: EntityId) -> Option<Entity> {
+        if id == EntityId::PLAYER {
+            return None;
+        }
+        self.search(id).ok().map(|i| self.entities.remove(i).1)
+    }
+
+    /// Put an entity back with a known id, like one read from a packet or file.
+    /// Replaces the one with the same id.
+    pub fn insert(&mut self, id: EntityId, entity: Entity) {
+        match self.search(id) {
+            Ok(i) => self.entities[i].1 = entity,
+            Err(i) => self.entities.insert(i, (id, entity)),
+        }
+        self.next_id = self.next_id.max(id.0 + 1);
+    }
+
+    /// Ids below this are never handed out again, even if nothing has them now.
+    pub fn set_next_id(&mut self, next_id: EntityId) {
+        self.next_id = next_id.0.max(self.newest().map_or(0, |id| id.0 + 1));
+    }
+
+    fn newest(&self) -> Option<EntityId> {
+        self.entities.last().map(|(id, _)| *id)
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Entity> {
+        self.search(id).ok().map(|i| &self.entities[i].1)
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        self.search(id).ok().map(|i| &mut self.entities[i].1)
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.search(id).is_ok()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (EntityId, &Entity)> {
+        self.entities.iter().map(|(id, entity)| (*id, entity))
+    }
+
+    /// Despawn everything `keep` returns false for, except the player like
+    /// [`Entities::despawn`].
+    pub fn retain(&mut self, mut keep: impl FnMut(EntityId, &Entity) -> bool) {
+        self.entities
+            .retain(|(id, entity)| *id == EntityId::PLAYER || keep(*id, entity));
+    }
+
+    pub fn iter_mut(&mut self) -> impl DoubleEndedIterator<Item = (EntityId, &mut Entity)> {
+        self.entities.iter_mut().map(|(id, entity)| (*id, entity))
+    }
+}
+
+/// u8 (0 = player, 1 = npc, 2 = projectile), projectiles follow with ticks left varint,
+/// subtick u8. Same in recordings and packets.
+pub(crate) fn write_kind(w: &mut impl Write, kind: EntityKind) -> io::Result<()> {
+    match kind {
+        EntityKind::Player => w.write_all(&[0]),
+        EntityKind::Npc => w.write_all(&[1]),
+        EntityKind::Projectile { ticks_left, subtick } => {
+            w.write_all(&[2])?;
+            write_varint(w, ticks_left as u32)?;
+            w.write_all(&[subtick])
+        }
+    }
+}
+
+pub(crate) fn read_kind(r: &mut impl Read) -> io::Result<EntityKind> {
+    let [kind] = read_bytes(r)?;
+    match kind {
+        0 => Ok(EntityKind::Player),
+        1 => Ok(EntityKind::Npc),
+        2 => {
+            let ticks_left =
+                u16::try_from(read_varint(r)?).map_err(|_| invalid("projectile lives too long"))?;
+            let [subtick] = read_bytes(r)?;
+            Ok(EntityKind::Projectile { ticks_left, subtick })
+        }
+        _ => Err(invalid("unknown entity kind")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_ids() {
+        let mut entities = Entities::default();
+        let player = entities.spawn(Entity::new(EntityKind::Player, Vec2::ZERO));
+        let a = entities.spawn(Entity::new(EntityKind::Npc, Vec2::X));
+        let b = entities.spawn(Entity::new(EntityKind::Npc, Vec2::Y));
+        assert_eq!(player, EntityId::PLAYER);
+
+        // ids aren't reused after despawning, even the newest
+        assert_eq!(entities.despawn(b).unwrap().movement.pos, Vec2::Y);
+        assert!(entities.despawn(b).is_none());
+        let c = entities.spawn(Entity::new(EntityKind::Npc, Vec2::ONE));
+        assert!(c > b);
+        assert_eq!(entities.get(a).unwrap().movement.pos, Vec2::X);
+        assert_eq!(entities.iter().map(|(id, _)| id).collect::<Vec<_>>(), [player, a, c]);
+
+        // inserting keeps them sorted
+        let mut copy = Entities::default();
+        for (id, entity) in entities.iter().rev() {
+            copy.insert(id, entity.clone());
+        }
+        copy.set_next_id(entities.next_id());
+        assert_eq!(copy, entities);
+        // the player never goes away
+        assert!(entities.despawn(player).is_none());
+        entities.retain(|_, _| false);
+        assert_eq!(entities.iter().map(|(id, _)| id).collect::<Vec<_>>(), [player]);
+    }
+}
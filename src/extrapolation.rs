@@ -0,0 +1,161 @@
+use macroquad::math::Vec2;
+
+use crate::movement::InterpolationStrategy;
+use crate::state::{GameState, TickNumber, TickSettings};
+
+/// Keeps the player moving on screen when ticks stop coming (lag, paused updates),
+/// then smoothly blends back to the real position once they come again.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Extrapolator {
+    pub enabled: bool,
+    /// Longest to extrapolate for in seconds, the player stops after this.
+    pub max_secs: f32,
+    /// About how long blending back to the real position takes in seconds.
+    pub blend_secs: f32,
+    /// How many ticks past the current tick the last position was, 0.0 if not extrapolating.
+    pub extrapolated_ticks: f32,
+    /// Offset from the real position still being blended away.
+    correction: Vec2,
+    /// Where the player was drawn last time.
+    last_pos: Option<Vec2>,
+    last_tick_number: TickNumber,
+}
+
+impl Extrapolator {
+    /// "extrapolation for 0.25s max"
+    pub const DEFAULT_MAX_SECS: f32 = 0.25;
+    const DEFAULT_BLEND_SECS: f32 = 0.1;
+
+    pub fn is_extrapolating(&self) -> bool {
+        self.extrapolated_ticks > 0.0
+    }
+
+    /// Where to draw the player this frame. `tick_progress` can go over 1.0 when ticks
+    /// are late, that's when extrapolating happens. Below 0.0 draws older ticks from the
+    /// buffer, see [`GlobalState::cursor_progress`](crate::state::GlobalState::cursor_progress).
+    pub fn update(
+        &mut self, game: &GameState, tick_settings: &TickSettings, strategy: InterpolationStrategy,
+        tick_progress: f32, delta_time: f32,
+    ) -> Vec2 {
+        let current = &game.current_tick().player();
+        let was_extrapolating = self.is_extrapolating();
+
+        let pos = if self.enabled && tick_progress > 1.0 {
+            let max_ticks = self.max_secs * tick_settings.tps;
+            self.extrapolated_ticks = (tick_progress - 1.0).min(max_ticks);
+            current.extrapolate(self.extrapolated_ticks, tick_settings.speed_factor, strategy)
+        } else {
+            self.extrapolated_ticks = 0.0;
+            let view = game.view(1.0 - tick_progress.min(1.0));
+            let next = &view.next.player().movement;
+            let before = view.before.map(|tick| &tick.player().movement);
+            view.prev.player().movement.interp_before(next, before, view.t, strategy)
+        };
+
+        // real tick came in, guessed wrong, keep drawing where the guess was and blend back
+        let new_tick = game.tick_number != self.last_tick_number;
+        self.last_tick_number = game.tick_number;
+        if new_tick && was_extrapolating {
+            if let Some(last_pos) = self.last_pos {
+                self.correction = last_pos - pos;
+            }
+        }
+        self.correction *= (-delta_time / self.blend_secs).exp();
+
+        let pos = pos + self.correction;
+        self.last_pos = Some(pos);
+        pos
+    }
+}
+
+impl Default for Extrapolator {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_secs: Self::DEFAULT_MAX_SECS,
+            blend_secs: Self::DEFAULT_BLEND_SECS,
+            extrapolated_ticks: 0.0,
+            correction: Vec2::ZERO,
+            last_pos: None,
+            last_tick_number: TickNumber::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Simulation;
+
+    #[test]
+    fn extrapolate_then_blend_back() {
+        const DT: f32 = 1.0 / 144.0;
+        let mut sim = Simulation::new(30.0, Vec2::ZERO).unwrap();
+        let mut extrapolator = Extrapolator::default();
+        let frame = |sim: &mut Simulation, extrapolator: &mut Extrapolator| {
+            sim.frame(DT, &mut |_| Vec2::X, false);
+            let progress = sim.global.render_progress();
+            let strategy = sim.global.interp_strategy;
+            extrapolator.update(&sim.game, &sim.global.tick_settings, strategy, progress, DT)
+        };
+
+        let mut last = Vec2::ZERO;
+        for _ in 0..144 {
+            last = frame(&mut sim, &mut extrapolator);
+        }
+
+        // keeps moving for max_secs after the ticks stop, then stops
+        sim.global.pause_updates = true;
+        let stalled = sim.game.current_tick().player().movement.pos;
+        for _ in 0..144 {
+            let pos = frame(&mut sim, &mut extrapolator);
+            assert!(pos.x >= last.x, "{} {}", pos, last);
+            last = pos;
+        }
+        assert!(extrapolator.is_extrapolating());
+        assert!(last.x > stalled.x);
+        assert_eq!(last, frame(&mut sim, &mut extrapolator));
+
+        // ticks resume from where they stopped, no teleporting back
+        sim.global.pause_updates = false;
+        for _ in 0..144 {
+            let pos = frame(&mut sim, &mut extrapolator);
+            assert!(pos.distance(last) < 20.0, "{} {}", pos, last);
+            last = pos;
+        }
+        assert!(!extrapolator.is_extrapolating());
+    }
+
+    #[test]
+    fn interp_delay() {
+        const DT: f32 = 1.0 / 30.0;
+        let mut sim = Simulation::new(30.0, Vec2::ZERO).unwrap();
+        let mut extrapolator = Extrapolator::default();
+        for _ in 0..30 {
+            sim.frame(DT, &mut |_| Vec2::X, false);
+        }
+        assert!(sim.global.set_interp_delay(1000.0).is_err());
+        sim.global.set_interp_delay_secs(2.5 * DT).unwrap();
+        assert!((sim.global.interp_delay - 2.5).abs() < 1e-4);
+
+        // halfway between 3 and 4 ticks back
+        let progress = sim.global.cursor_progress();
+        let strategy = InterpolationStrategy::Linear;
+        let pos = extrapolator.update(&sim.game, &sim.global.tick_settings, strategy, progress, DT);
+        let newer = sim.game.get_prev_tick(3).unwrap().player().movement.pos;
+        let older = sim.game.get_prev_tick(4).unwrap().player().movement.pos;
+        assert!(pos.distance((newer + older) / 2.0) < 0.1, "{} {} {}", pos, newer, older);
+
+        // late ticks are covered by the delay, no extrapolating
+        sim.global.pause_updates = true;
+        sim.frame(DT, &mut |_| Vec2::X, false);
+        let progress = sim.global.cursor_progress();
+        extrapolator.update(&sim.game, &sim.global.tick_settings, strategy, progress, DT);
+        assert!(!extrapolator.is_extrapolating());
+
+        // shrinking the buffer shrinks the delay
+        let game = &mut sim.game;
+        sim.global.set_buffer_secs(game, 2.5 * DT).unwrap();
+        assert_eq!(sim.global.interp_delay, 1.0);
+    }
+}
@@ -0,0 +1,223 @@
+//! Lag compensated hit detection. A client shoots at what it sees, which is the past by
+//! the time the server hears about it, so the server checks against the past too.
+//!
+//! The client reports which ticks it was drawing between, the server rebuilds the same
+//! positions from its tick buffer and tests against those.
+
+use macroquad::math::Vec2;
+
+use crate::entity::{EntityId, EntityKind};
+use crate::movement::InterpolationStrategy;
+use crate::state::{GameState, TickNumber, TickSettings};
+use crate::world::World;
+
+/// What a client was looking at: `fraction` of the way from the tick before `tick_number`
+/// to `tick_number`.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct ViewTime {
+    pub tick_number: TickNumber,
+    pub fraction: f32,
+}
+
+impl ViewTime {
+    /// Ticks since tick 0, fractional.
+    pub fn ticks(&self) -> f64 {
+        self.tick_number.0 as f64 - 1.0 + self.fraction as f64
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec2,
+    /// Normalized.
+    pub dir: Vec2,
+    pub max_len: f32,
+}
+
+impl Ray {
+    pub fn new(origin: Vec2, dir: Vec2, max_len: f32) -> Self {
+        Self { origin, dir: dir.normalize_or_zero(), max_len }
+    }
+}
+
+/// Where a ray hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub id: EntityId,
+    /// Where it was at the view time.
+    pub target: Vec2,
+    /// First point of the ray inside it.
+    pub point: Vec2,
+    /// Along the ray.
+    pub distance: f32,
+}
+
+/// Distance along `ray` to where it enters the circle, if it does.
+/// Starting inside counts as a hit at 0.0.
+pub fn ray_circle(ray: &Ray, center: Vec2, radius: f32) -> Option<f32> {
+    let to_center = center - ray.origin;
+    let closest = to_center.dot(ray.dir);
+    let dist_sq = to_center.length_squared() - closest * closest;
+    let radius_sq = radius * radius;
+    if dist_sq > radius_sq {
+        return None;
+    }
+    let half_chord = (radius_sq - dist_sq).sqrt();
+    let enter = if to_center.length_squared() <= radius_sq { 0.0 } else { closest - half_chord };
+    (enter >= 0.0 && enter <= ray.max_len).then_some(enter)
+}
+
+/// How far back the server is willing to look.
+///
+/// Only an API so far, [`Server`](crate::net::Server) doesn't use it yet because input
+/// packets don't carry a [`ViewTime`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct LagCompensation {
+    /// Longest rewind in seconds, older view times are checked at this age instead.
+    /// Can't be more than the tick buffer holds.
+    pub max_rewind_secs: f32,
+    pub strategy: InterpolationStrategy,
+}
+
+impl LagCompensation {
+    /// Rewinds as far as the tick buffer goes, older ticks aren't there to check anyway.
+    /// Lower `max_rewind_secs` to stop laggy clients from shooting too far into the past.
+    pub fn new(tick_settings: &TickSettings) -> Self {
+        Self { max_rewind_secs: tick_settings.buffer_secs, strategy: Default::default() }
+    }
+
+    /// Most ticks to rewind, limited by `max_rewind_secs` and the tick buffer.
+    pub fn max_rewind_ticks(&self, tick_settings: &TickSettings, game: &GameState) -> f32 {
+        let secs = self.max_rewind_secs.min(tick_settings.buffer_secs);
+        // needs 2 ticks to interpolate between
+        (secs * tick_settings.tps).min(game.buffer().len().saturating_sub(2) as f32)
+    }
+
+    /// Ticks to rewind from the current tick to `view`, clamped to what's allowed.
+    pub fn rewind_ticks(
+        &self, tick_settings: &TickSettings, game: &GameState, view: ViewTime,
+    ) -> f32 {
+        let now = ViewTime { tick_number: game.tick_number, fraction: 1.0 };
+        let back = (now.ticks() - view.ticks()).max(0.0) as f32;
+        back.min(self.max_rewind_ticks(tick_settings, game))
+    }
+
+    /// Where everything was drawn at `view`, rebuilt the same way the client draws it.
+    /// Projectiles fired later in the tick aren't there yet.
+    pub fn entities_at(
+        &self, tick_settings: &TickSettings, game: &GameState, world: &World, view: ViewTime,
+    ) -> Vec<(EntityId, EntityKind, Vec2)> {
+        let ticks = game.view(self.rewind_ticks(tick_settings, game, view));
+        let (t, prev, before) = (ticks.t, ticks.prev, ticks.before);
+        ticks
+            .next
+            .entities
+            .iter()
+            .filter_map(|(id, entity)| {
+                let pos = match prev.entities.get(id) {
+                    Some(prev) => {
+                        let before = before.and_then(|before| before.entities.get(id));
+                        entity.interp(prev, before, t, self.strategy, world)
+                    }
+                    None => entity.spawn_pos(t)?,
+                };
+                Some((id, entity.kind, pos))
+            })
+            .collect()
+    }
+
+    /// Where `id` was drawn at `view`, if it existed.
+    pub fn entity_at(
+        &self, tick_settings: &TickSettings, game: &GameState, world: &World, view: ViewTime,
+        id: EntityId,
+    ) -> Option<Vec2> {
+        let entities = self.entities_at(tick_settings, game, world, view);
+        entities.into_iter().find(|(entity, ..)| *entity == id).map(|(.., pos)| pos)
+    }
+
+    /// Shoot `ray` at everything as it was at `view`, except `shooter` and projectiles.
+    /// Returns the closest hit.
+    pub fn raycast(
+        &self, tick_settings: &TickSettings, game: &GameState, world: &World, view: ViewTime,
+        ray: &Ray, shooter: Option<EntityId>,
+    ) -> Option<Hit> {
+        self.entities_at(tick_settings, game, world, view)
+            .into_iter()
+            .filter(|(id, kind, _)| Some(*id) != shooter && kind.is_hittable())
+            .filter_map(|(id, kind, target)| {
+                let distance = ray_circle(ray, target, kind.radius())?;
+                Some(Hit { id, target, point: ray.origin + ray.dir * distance, distance })
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    /// Everything but projectiles a circle, like an explosion, overlapped as it was at `view`.
+    pub fn overlaps(
+        &self, tick_settings: &TickSettings, game: &GameState, world: &World, view: ViewTime,
+        center: Vec2, radius: f32,
+    ) -> Vec<EntityId> {
+        self.entities_at(tick_settings, game, world, view)
+            .into_iter()
+            .filter(|(_, kind, target)| {
+                kind.is_hittable() && target.distance(center) <= radius + kind.radius()
+            })
+            .map(|(id, ..)| id)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Simulation, TickInput};
+
+    #[test]
+    fn ray_circle_cases() {
+        let ray = Ray::new(Vec2::ZERO, Vec2::X, 100.0);
+        assert_eq!(ray_circle(&ray, Vec2::new(50.0, 0.0), 10.0), Some(40.0));
+        assert!(ray_circle(&ray, Vec2::new(50.0, 11.0), 10.0).is_none());
+        assert!(ray_circle(&ray, Vec2::new(-50.0, 0.0), 10.0).is_none());
+        assert!(ray_circle(&ray, Vec2::new(150.0, 0.0), 10.0).is_none());
+        assert_eq!(ray_circle(&ray, Vec2::new(5.0, 0.0), 10.0), Some(0.0));
+    }
+
+    #[test]
+    fn rewind() {
+        let mut sim = Simulation::new(30.0, Vec2::ZERO).unwrap();
+        for _ in 0..30 {
+            sim.tick_with(TickInput { wish_dir: Vec2::X, reset: false, ..Default::default() });
+        }
+        let settings = sim.global.tick_settings.clone();
+        let lag_comp = LagCompensation {
+            strategy: InterpolationStrategy::Linear,
+            ..LagCompensation::new(&settings)
+        };
+        let (game, world) = (&sim.game, sim.world());
+        let pos = |back: usize| game.get_prev_tick(back).unwrap().player().movement.pos;
+        let player = Some(EntityId::PLAYER);
+
+        // what the client saw 3.5 ticks ago
+        let view = ViewTime { tick_number: game.tick_number.saturating_sub(3), fraction: 0.5 };
+        assert_eq!(lag_comp.rewind_ticks(&settings, game, view), 3.5);
+        let then = lag_comp.entity_at(&settings, game, world, view, EntityId::PLAYER).unwrap();
+        assert!(then.distance((pos(3) + pos(4)) / 2.0) < 1e-3);
+
+        // a ray at where they were hits, at where they are now misses
+        let ray = |target: Vec2| Ray::new(target - Vec2::new(0.0, 100.0), Vec2::Y, 200.0);
+        let hit = lag_comp.raycast(&settings, game, world, view, &ray(then), None).unwrap();
+        assert_eq!(hit.id, EntityId::PLAYER);
+        assert!((hit.distance - 90.0).abs() < 1e-3);
+        assert!(lag_comp.raycast(&settings, game, world, view, &ray(pos(0)), None).is_none());
+        assert!(lag_comp.raycast(&settings, game, world, view, &ray(then), player).is_none());
+        let overlapped =
+            lag_comp.overlaps(&settings, game, world, view, then + Vec2::Y * 15.0, 6.0);
+        assert_eq!(overlapped, [EntityId::PLAYER]);
+
+        // too old gets clamped to the window
+        let lag_comp = LagCompensation { max_rewind_secs: 0.1, ..lag_comp };
+        let view = ViewTime { tick_number: TickNumber(1), fraction: 0.0 };
+        assert!((lag_comp.rewind_ticks(&settings, game, view) - 3.0).abs() < 1e-4);
+        let current = ViewTime { tick_number: game.tick_number + 5, fraction: 0.0 };
+        assert_eq!(lag_comp.rewind_ticks(&settings, game, current), 0.0);
+    }
+}
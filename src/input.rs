@@ -0,0 +1,187 @@
+use std::sync::mpsc::Receiver;
+
+use macroquad::input::{
+    is_key_down, is_mouse_button_pressed, mouse_position, KeyCode, MouseButton,
+};
+use macroquad::math::Vec2;
+
+/// One reading of a wish direction.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct InputSample {
+    /// Wish direction, length 0.0..=1.0.
+    pub dir: Vec2,
+    /// When it was read, seconds since the simulation started.
+    pub time: f64,
+    /// How long it was held for in seconds, time since the previous sample.
+    /// Filled in by [`Simulation`](crate::state::Simulation) when buffered.
+    pub duration: f32,
+    /// Where to shoot at, if fire was pressed.
+    pub fire: Option<Vec2>,
+}
+
+impl InputSample {
+    pub fn new(dir: Vec2, time: f64) -> Self {
+        Self { dir, time, duration: 0.0, fire: None }
+    }
+
+    pub fn with_duration(dir: Vec2, time: f64, duration: f32) -> Self {
+        Self { dir, time, duration, fire: None }
+    }
+
+    /// Also shoot towards `target`.
+    pub fn with_fire(self, target: Vec2) -> Self {
+        Self { fire: Some(target), ..self }
+    }
+}
+
+/// Something that produces wish directions. Polled as often as possible and the samples
+/// are averaged every tick by [`AveragingStrategy`](crate::player::AveragingStrategy),
+/// so keyboards, bots, replays and remote clients all go through the same pipeline.
+pub trait InputSource {
+    /// Read the current input. `time` is seconds since the simulation started.
+    /// Returns `None` if there is no new input, like when nothing arrived from the network.
+    fn sample(&mut self, time: f64) -> Option<InputSample>;
+}
+
+/// Any `FnMut(time) -> dir` is an input source, handy for bots and tests.
+impl<F: FnMut(f64) -> Vec2> InputSource for F {
+    fn sample(&mut self, time: f64) -> Option<InputSample> {
+        Some(InputSample::new(self(time), time))
+    }
+}
+
+/// WASD and arrow keys, left click shoots at the mouse. Needs a macroquad window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct KeyboardInput;
+
+impl KeyboardInput {
+    pub fn desired_dir() -> Vec2 {
+        let mut dir = Vec2::ZERO;
+        if is_key_down(KeyCode::W) || is_key_down(KeyCode::Up) {
+            dir.y += -1.0;
+        }
+        if is_key_down(KeyCode::S) || is_key_down(KeyCode::Down) {
+            dir.y += 1.0;
+        }
+        if is_key_down(KeyCode::A) || is_key_down(KeyCode::Left) {
+            dir.x += -1.0;
+        }
+        if is_key_down(KeyCode::D) || is_key_down(KeyCode::Right) {
+            dir.x += 1.0;
+        }
+        dir.normalize_or_zero()
+    }
+}
+
+impl InputSource for KeyboardInput {
+    fn sample(&mut self, time: f64) -> Option<InputSample> {
+        let sample = InputSample::new(Self::desired_dir(), time);
+        if is_mouse_button_pressed(MouseButton::Left) {
+            return Some(sample.with_fire(mouse_position().into()));
+        }
+        Some(sample)
+    }
+}
+
+/// Gamepad style analog stick. Set `stick` to the raw axis values whenever the
+/// device reports them.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct AnalogStick {
+    /// Raw axis values, about -1.0..=1.0 each.
+    pub stick: Vec2,
+    /// Stick lengths below this read as zero.
+    pub deadzone: f32,
+}
+
+impl AnalogStick {
+    pub fn new(deadzone: f32) -> Self {
+        Self { stick: Vec2::ZERO, deadzone }
+    }
+
+    /// Radial deadzone, rescaled so just outside the deadzone starts at 0.0.
+    /// Clamped to length 1.0 because square gates can report about 1.41 in the corners.
+    pub fn desired_dir(&self) -> Vec2 {
+        let len = self.stick.length();
+        if len <= self.deadzone {
+            return Vec2::ZERO;
+        }
+        let scaled_len = ((len - self.deadzone) / (1.0 - self.deadzone)).min(1.0);
+        self.stick / len * scaled_len
+    }
+}
+
+impl InputSource for AnalogStick {
+    fn sample(&mut self, time: f64) -> Option<InputSample> {
+        Some(InputSample::new(self.desired_dir(), time))
+    }
+}
+
+/// Plays back a list of samples sorted by time. Each sample is held until the next one.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct ScriptedInput {
+    samples: Vec<InputSample>,
+    index: usize,
+}
+
+impl ScriptedInput {
+    pub fn new(mut samples: Vec<InputSample>) -> Self {
+        samples.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { samples, index: 0 }
+    }
+
+    /// If every sample has been played.
+    pub fn is_finished(&self) -> bool {
+        self.index + 1 >= self.samples.len()
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn sample(&mut self, time: f64) -> Option<InputSample> {
+        while self.samples.get(self.index + 1).is_some_and(|next| next.time <= time) {
+            self.index += 1;
+        }
+        let current = self.samples.get(self.index).filter(|sample| sample.time <= time)?;
+        Some(InputSample::new(current.dir, time))
+    }
+}
+
+/// Samples sent from somewhere else, like another thread or a network socket.
+/// Only the newest sample is used if several arrived since the last poll.
+#[derive(Debug)]
+pub struct ChannelInput(pub Receiver<InputSample>);
+
+impl InputSource for ChannelInput {
+    fn sample(&mut self, _time: f64) -> Option<InputSample> {
+        self.0.try_iter().last()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripted() {
+        let mut script = ScriptedInput::new(vec![
+            InputSample::new(Vec2::Y, 1.0),
+            InputSample::new(Vec2::X, 0.5),
+        ]);
+        assert_eq!(script.sample(0.0), None);
+        assert_eq!(script.sample(0.5).unwrap().dir, Vec2::X);
+        assert_eq!(script.sample(0.9).unwrap().dir, Vec2::X);
+        assert!(!script.is_finished());
+        assert_eq!(script.sample(2.0).unwrap(), InputSample::new(Vec2::Y, 2.0));
+        assert!(script.is_finished());
+    }
+
+    #[test]
+    fn analog_deadzone() {
+        let mut stick = AnalogStick::new(0.2);
+        stick.stick = Vec2::new(0.1, 0.1);
+        assert_eq!(stick.desired_dir(), Vec2::ZERO);
+        stick.stick = Vec2::new(1.0, 1.0);
+        assert!((stick.desired_dir().length() - 1.0).abs() < 1e-6);
+        stick.stick = Vec2::new(0.6, 0.0);
+        assert!((stick.desired_dir().x - 0.5).abs() < 1e-6);
+    }
+}
@@ -1,7 +1,17 @@
+pub mod config;
+pub mod entity;
+pub mod extrapolation;
+pub mod hit;
+pub mod input;
+pub mod metrics;
 pub mod movement;
+pub mod net;
 pub mod player;
+pub mod prediction;
+pub mod replay;
 pub mod state;
 pub mod time;
+pub mod world;
 
 use macroquad::prelude::*;
 
@@ -34,6 +44,22 @@ fn lerp_precise2(a: Vec2, b: Vec2, t: f32) -> Vec2 {
     Vec2::new(lerp_precise(a.x, b.x, t), lerp_precise(a.y, b.y, t))
 }
 
+/// Cubic Hermite spline. Exactly `p0` when `t` is 0.0, exactly `p1` when `t` is 1.0,
+/// with the slope `m0` at the start and `m1` at the end.
+/// Tangents are per the whole 0..=1 range of `t`, so a velocity in units per second
+/// needs to be multiplied by the length of the segment in seconds first.
+///
+/// See <https://en.wikipedia.org/wiki/Cubic_Hermite_spline#Unit_interval_(0,_1)>
+fn hermite2(p0: Vec2, m0: Vec2, p1: Vec2, m1: Vec2, t: f32) -> Vec2 {
+    let t_2 = t * t;
+    let t_3 = t_2 * t;
+    let h00 = 2.0 * t_3 - 3.0 * t_2 + 1.0;
+    let h10 = t_3 - 2.0 * t_2 + t;
+    let h01 = -2.0 * t_3 + 3.0 * t_2;
+    let h11 = t_3 - t_2;
+    h00 * p0 + h10 * m0 + h01 * p1 + h11 * m1
+}
+
 // NOTE: personally: lerp_precise is better in most cases, but lerp_fast good for similar values
 /// Linear interpolation. About `a` when `t` is 0.0, about `b` when `t` is 1.0. Monotinic.
 /// Not guarenteed to be exact. Extrapolates when outside of 0..=1.
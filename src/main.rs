@@ -1,19 +1,74 @@
 mod dbg;
 
-use std::time::Instant;
+use std::fmt::Display;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 
-use interp_test::movement::Movement;
-use interp_test::state::{GameState, GlobalState};
+use interp_test::config::Config;
+use interp_test::entity::EntityId;
+use interp_test::input::InputSource;
+use interp_test::input::KeyboardInput;
+use interp_test::net::{Client, DEFAULT_PORT};
+use interp_test::player::Player;
+use interp_test::prediction::Prediction;
+use interp_test::replay::Recording;
+use interp_test::state::update_thread::{Command, UpdateThread};
+use interp_test::state::{GameState, GlobalState, Simulation, View};
 use interp_test::time::Timings;
-use interp_test::{dbg_arrow, player::Player, DBG_INTERP, DBG_NOW, DBG_PREV};
+use interp_test::world::World;
+use interp_test::{dbg_arrow, DBG_INTERP, DBG_NOW, DBG_PREV};
 use macroquad::prelude::*;
 use macroquad::window::{screen_height, screen_width};
 
 #[macroquad::main("interp test")]
 async fn main() {
-    let mut global_state = GlobalState::new(30.0).unwrap();
-    let mut game_state = GameState::new(global_state.tick_settings.buffer_len);
-    game_state.init();
+    let args = std::env::args().collect::<Vec<_>>();
+    let config_path = args
+        .iter()
+        .position(|arg| arg == "--config")
+        .and_then(|i| args.get(i + 1))
+        .map_or(Config::PATH, |path| path.as_str());
+    let config = load_config(config_path, &args);
+    let mut sim = Simulation::new(Config::DEFAULT_TPS, screen_center()).unwrap();
+    sim.set_world(World::arena());
+    // NOTE:PANIC: load_config only returns sane settings
+    config.apply(&mut sim.game, &mut sim.global).unwrap();
+    let mut keyboard = KeyboardInput;
+
+    // replay a recording, like one attached to a bug report
+    let mut last_recording = None;
+    if let Some(path) = args.iter().position(|arg| arg == "--replay").and_then(|i| args.get(i + 1))
+    {
+        match Recording::load(path) {
+            Ok(recording) => {
+                sim.start_playback(recording.clone());
+                last_recording = Some(recording);
+            }
+            Err(err) => eprintln!("Failed to load recording {}: {}", path, err),
+        }
+    }
+    let mut played_back = sim.playback.is_some();
+
+    // play on a server instead of locally, see src/bin/server.rs
+    let mut client = None;
+    if let Some(addr) = args.iter().position(|arg| arg == "--connect").and_then(|i| args.get(i + 1))
+    {
+        let addr =
+            if addr.contains(':') { addr.clone() } else { format!("{}:{}", addr, DEFAULT_PORT) };
+        match Client::connect(&addr) {
+            Ok(c) => client = Some(c),
+            Err(err) => eprintln!("Failed to connect to {}: {}", addr, err),
+        }
+    }
+    let mut prediction = Prediction::default();
+
+    // tick on another thread, only drawing what it sends back
+    let mut update_thread = None;
+    if client.is_none() && args.iter().any(|arg| arg == "--threaded") {
+        update_thread = Some(UpdateThread::spawn(sim.clone()));
+    }
+
+    // closing the window ends the loop like esc instead of the process, so settings get saved
+    prevent_quit();
 
     #[allow(clippy::field_reassign_with_default)] // to match other all other uses if Timings
     loop {
@@ -21,46 +76,180 @@ async fn main() {
         let mut current_timings = Timings::default();
         current_timings.start = Some(Instant::now());
 
-        let delta_time = get_frame_time();
-        let ready_to_update = global_state.update_timer.decrement(delta_time);
-        // HACK: prevents mega extrapolating when tps > fps
-        // currently: if fps > tps, tps = fps
-        // TODO: render thread
-        global_state.update_timer.decrement(0.0);
-        global_state.update_timer.decrement(0.0);
-        global_state.update_timer.decrement(0.0);
-        global_state.update_timer.decrement(0.0);
-        global_state.update_timer.decrement(0.0);
-        global_state.update_timer.decrement(0.0);
-        global_state.update_timer.decrement(0.0);
+        let ticks_due =
+            if update_thread.is_some() { 0 } else { sim.advance_clock(get_frame_time()) };
 
         // Input handling
+        let settings_before = sim.global.tick_settings.clone();
+        let pause_updates_before = sim.global.pause_updates;
         // HACK: ugly bool
-        let close = pre_update(&mut game_state, &mut global_state);
+        let close = pre_update(&mut sim.game, &mut sim.global);
         if close {
             break;
         }
+        if is_key_pressed(KeyCode::R) {
+            reset_settings(&mut sim, &config, client.is_some());
+        }
+        if update_thread.is_none() {
+            handle_recording(&mut sim, &mut last_recording);
+            played_back |= sim.playback.is_some();
+        } else if is_key_pressed(KeyCode::K) || is_key_pressed(KeyCode::L) {
+            let result = Err("recording and replaying don't work with --threaded");
+            refuse(&mut sim.global, result);
+        }
+        handle_tick_control(&mut sim, update_thread.as_ref());
+        if is_key_pressed(KeyCode::M) {
+            sim.compact();
+            if let Some(update_thread) = &update_thread {
+                update_thread.send(Command::Compact);
+            }
+        }
+        if update_thread.is_none() {
+            sim.poll_input(&mut keyboard); // as close to update as possible
+        }
         current_timings.pre_update = Some(Instant::now());
 
         // Update
-        if ready_to_update {
-            update(&mut game_state, &global_state);
-            global_state.input_buffer.clear()
+        // the server's spawn comes with its snapshots, a replay's with its ticks
+        if client.is_none() && sim.playback.is_none() {
+            sim.set_spawn(screen_center());
+        }
+        if let Some(update_thread) = &mut update_thread {
+            if sim.global.tick_settings != settings_before {
+                update_thread.send(Command::Settings(sim.global.tick_settings.clone()));
+            }
+            if sim.global.pause_updates != pause_updates_before {
+                update_thread.send(Command::PauseUpdates(sim.global.pause_updates));
+            }
+            update_thread.set_spawn(sim.spawn());
+            update_thread.set_reset(is_key_down(KeyCode::Space));
+            if let Some(sample) = keyboard.sample(update_thread.clock.now()) {
+                update_thread.send_input(sample);
+            }
+            update_thread.receive(&mut sim);
+        } else if let Some(client) = &mut client {
+            handle_client_keys(&mut sim, client, &mut prediction);
+            // server does the real ticking, predict them locally or show what comes back
+            for _ in 0..ticks_due {
+                let input = sim.take_input(is_key_down(KeyCode::Space));
+                if let Err(err) = client.send_input(input) {
+                    eprintln!("Failed to send input: {}", err);
+                }
+                if prediction.enabled {
+                    prediction.predict(&mut sim, input, client.sequence);
+                }
+            }
+            match client.receive() {
+                Ok(snapshots) => {
+                    for snapshot in snapshots.iter() {
+                        if prediction.enabled {
+                            prediction.reconcile(&mut sim, snapshot);
+                        } else {
+                            sim.apply_snapshot(snapshot);
+                        }
+                    }
+                }
+                Err(err) => eprintln!("Failed to receive snapshots: {}", err),
+            }
+        } else {
+            for _ in 0..ticks_due {
+                sim.tick(is_key_down(KeyCode::Space));
+            }
         }
         current_timings.update = Some(Instant::now());
 
         // Drawing
-        let mut tick_progress = global_state.tick_progress();
-        if global_state.dont_interpolate {
+        let mut tick_progress = sim.global.cursor_progress();
+        if sim.global.dont_interpolate {
             tick_progress = 1.0;
         }
-        draw(&game_state, &global_state, tick_progress);
+        let GlobalState { extrapolator, tick_settings, interp_strategy, tick_control, .. } =
+            &mut sim.global;
+        let (ticks_back, player_pos) = if tick_control.paused {
+            // looking back through the buffer, nothing to extrapolate
+            let view = sim.game.view(tick_control.view_back);
+            let next = &view.next.player().movement;
+            let before = view.before.map(|tick| &tick.player().movement);
+            let pos =
+                view.prev
+                    .player()
+                    .movement
+                    .interp_before(next, before, view.t, *interp_strategy);
+            (tick_control.view_back, pos)
+        } else {
+            let mut player_pos = extrapolator.update(
+                &sim.game,
+                tick_settings,
+                *interp_strategy,
+                tick_progress,
+                get_frame_time(),
+            );
+            if client.is_some() {
+                player_pos += prediction.update(get_frame_time());
+            }
+            (1.0 - tick_progress.min(1.0), player_pos)
+        };
+        draw(&sim, ticks_back, player_pos);
+        draw_replay_status(&sim);
+        if let Some(client) = &client {
+            draw_client_status(&sim, client, &prediction);
+        }
         current_timings.draw = Some(Instant::now());
 
         // has forced vsync :/ disable on Linux with `vblank_mode=0 cargo run`
         next_frame().await;
         current_timings.waiting = Some(Instant::now());
-        global_state.timings = current_timings;
+        sim.global.timings = current_timings;
+    }
+
+    // a replay brings its own tick settings, the server picks them when connected
+    if played_back {
+        println!("Not saving settings, a replay changed them");
+    } else if client.is_none() {
+        if let Err(err) = Config::from_global(&sim.global).save(config_path) {
+            eprintln!("Failed to save settings {}: {}", config_path, err);
+        }
+    }
+}
+
+/// Settings from the file at `path` if there is one, then overridden by `args`.
+fn load_config(path: &str, args: &[String]) -> Config {
+    let mut config = match Config::load(path) {
+        Ok(config) => config,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Config::default(),
+        Err(err) => {
+            eprintln!("Failed to load settings {}, using defaults: {}", path, err);
+            Config::default()
+        }
+    };
+    if let Err(err) = config.set_from_args(args) {
+        eprintln!("Ignoring settings from the command line: {}", err);
+    }
+    config
+}
+
+fn screen_center() -> Vec2 {
+    Vec2::new(screen_width() / 2.0, screen_height() / 2.0)
+}
+
+/// Back to the settings from launch. Pausing and everything else that isn't a setting
+/// stays, and so do the tick settings when connected, the server picks those.
+fn reset_settings(sim: &mut Simulation, config: &Config, connected: bool) {
+    let mut config = config.clone();
+    if connected {
+        let current = Config::from_global(&sim.global);
+        config.tps = current.tps;
+        config.buffer_secs = current.buffer_secs;
+        config.timescale = current.timescale;
+    }
+    let result = config.apply(&mut sim.game, &mut sim.global);
+    refuse(&mut sim.global, result);
+}
+
+/// Show why a settings change didn't happen in the dbg info.
+fn refuse<E: Display>(global_state: &mut GlobalState, result: Result<(), E>) {
+    if let Err(err) = result {
+        global_state.dbg_refused = Some((err.to_string(), get_time()));
     }
 }
 
@@ -69,6 +258,7 @@ fn pre_update(game: &mut GameState, global_state: &mut GlobalState) -> bool {
     if (is_key_down(KeyCode::LeftControl) || is_key_down(KeyCode::RightControl))
         && (is_key_down(KeyCode::C) || is_key_down(KeyCode::Q))
         || is_key_down(KeyCode::Escape)
+        || is_quit_requested()
     {
         return true;
     }
@@ -103,19 +293,37 @@ fn pre_update(game: &mut GameState, global_state: &mut GlobalState) -> bool {
 
     // Modify tps
     if is_key_pressed(KeyCode::F3) {
-        _ = global_state.set_tps(game, global_state.tick_settings.tps - 5.0);
+        let result = global_state.set_tps(game, global_state.tick_settings.tps - 5.0);
+        refuse(global_state, result);
     }
 
     if is_key_pressed(KeyCode::F4) {
-        _ = global_state.set_tps(game, global_state.tick_settings.tps + 5.0);
+        let result = global_state.set_tps(game, global_state.tick_settings.tps + 5.0);
+        refuse(global_state, result);
     }
 
     if is_key_pressed(KeyCode::F5) {
-        _ = global_state.set_buffer_secs(game, global_state.tick_settings.buffer_secs - 0.05);
+        let secs = global_state.tick_settings.buffer_secs - 0.05;
+        let result = global_state.set_buffer_secs(game, secs);
+        refuse(global_state, result);
     }
 
     if is_key_pressed(KeyCode::F6) {
-        _ = global_state.set_buffer_secs(game, global_state.tick_settings.buffer_secs + 0.05);
+        let secs = global_state.tick_settings.buffer_secs + 0.05;
+        let result = global_state.set_buffer_secs(game, secs);
+        refuse(global_state, result);
+    }
+
+    // Modify interp delay
+    if is_key_pressed(KeyCode::F7) {
+        let delay = (global_state.interp_delay - 0.5).max(0.0);
+        let result = global_state.set_interp_delay(delay);
+        refuse(global_state, result);
+    }
+
+    if is_key_pressed(KeyCode::F8) {
+        let result = global_state.set_interp_delay(global_state.interp_delay + 0.5);
+        refuse(global_state, result);
     }
 
     // FIXME: spamming eats all ram (how??)
@@ -125,10 +333,6 @@ fn pre_update(game: &mut GameState, global_state: &mut GlobalState) -> bool {
     // }
 
     // Interp stuff
-    if is_key_pressed(KeyCode::R) {
-        // reset
-        *global_state = GlobalState::new(30.0).unwrap();
-    }
     if is_key_pressed(KeyCode::U) {
         global_state.dbg_hide_interp_info = !global_state.dbg_hide_interp_info;
     }
@@ -145,71 +349,210 @@ fn pre_update(game: &mut GameState, global_state: &mut GlobalState) -> bool {
             AveragingStrategy::Newest => AveragingStrategy::Mean,
             AveragingStrategy::Mean => AveragingStrategy::MeanIgnoreZero,
             AveragingStrategy::MeanIgnoreZero => AveragingStrategy::MeanNormalized,
-            AveragingStrategy::MeanNormalized => AveragingStrategy::Oldest,
+            AveragingStrategy::MeanNormalized => AveragingStrategy::TimeWeightedMean,
+            AveragingStrategy::TimeWeightedMean => AveragingStrategy::DecayingMean(0.05),
+            AveragingStrategy::DecayingMean(_) => AveragingStrategy::MeanIgnoreFirstXZeros(3),
+            AveragingStrategy::MeanIgnoreFirstXZeros(_) => AveragingStrategy::Oldest,
             _ => AveragingStrategy::Oldest,
         }
     }
-
-    handle_inputs(&mut global_state.input_buffer); // as close to update as possible
+    if is_key_pressed(KeyCode::X) {
+        global_state.pause_updates = !global_state.pause_updates;
+    }
+    if is_key_pressed(KeyCode::E) {
+        global_state.extrapolator.enabled = !global_state.extrapolator.enabled;
+    }
+    if is_key_pressed(KeyCode::Y) {
+        use interp_test::movement::InterpolationStrategy;
+        global_state.interp_strategy = match global_state.interp_strategy {
+            InterpolationStrategy::Linear => InterpolationStrategy::ConstantVelocity,
+            InterpolationStrategy::ConstantVelocity => InterpolationStrategy::ConstantAcceleration,
+            InterpolationStrategy::ConstantAcceleration => InterpolationStrategy::Curvy,
+            InterpolationStrategy::Curvy => InterpolationStrategy::Hermite,
+            InterpolationStrategy::Hermite => InterpolationStrategy::CatmullRom,
+            InterpolationStrategy::CatmullRom => InterpolationStrategy::Linear,
+        }
+    }
 
     false
 }
 
-fn update(game: &mut GameState, global_state: &GlobalState) {
-    game.advance_tick();
-    let speed_factor = global_state.tick_settings.speed_factor;
-    let player = &mut game.current_tick_mut().player;
-    let center = Vec2::new(screen_width() / 2.0, screen_height() / 2.0);
-    let wish_dir = global_state.avg_strategy.average(&global_state.input_buffer);
-    player.handle_movement(wish_dir, Player::accel(speed_factor));
+/// k to start/stop recording and save it, l to replay the last recording.
+fn handle_recording(sim: &mut Simulation, last_recording: &mut Option<Recording>) {
+    if is_key_pressed(KeyCode::K) {
+        if let Some(recording) = sim.stop_recording() {
+            let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            let path = format!("recording-{}.itr", secs);
+            match recording.save(&path) {
+                Ok(()) => println!("Saved recording to {}", path),
+                Err(err) => eprintln!("Failed to save recording {}: {}", path, err),
+            }
+            *last_recording = Some(recording);
+        } else {
+            sim.start_recording();
+        }
+    }
+
+    if is_key_pressed(KeyCode::L) {
+        if let Some(recording) = last_recording {
+            sim.stop_recording();
+            sim.start_playback(recording.clone());
+        }
+    }
+}
+
+/// b to pause, . and , to step a tick forward and back, hold shift to scrub,
+/// j to continue from the tick being looked at.
+/// Looking around is done locally, the update thread only needs to know what changes ticks.
+fn handle_tick_control(sim: &mut Simulation, update_thread: Option<&UpdateThread>) {
+    const SCRUB_TPS: f32 = 10.0;
+    if is_key_pressed(KeyCode::B) {
+        sim.toggle_pause();
+        if let Some(update_thread) = update_thread {
+            update_thread.send(Command::Pause(sim.global.tick_control.paused));
+        }
+    }
+    if is_key_pressed(KeyCode::J) {
+        if let Some(update_thread) = update_thread {
+            let back = sim.global.tick_control.view_back.round() as usize;
+            update_thread.send(Command::ResumeFrom(sim.game.tick_number.saturating_sub(back)));
+        }
+        sim.resume_from_view();
+    }
+
+    let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+    if shift {
+        if is_key_down(KeyCode::Comma) {
+            sim.scrub(SCRUB_TPS * get_frame_time());
+        }
+        if is_key_down(KeyCode::Period) {
+            sim.scrub(-SCRUB_TPS * get_frame_time());
+        }
+    } else {
+        if is_key_pressed(KeyCode::Comma) {
+            sim.step_back();
+        }
+        if is_key_pressed(KeyCode::Period) {
+            let control = &sim.global.tick_control;
+            match update_thread {
+                // the new tick comes back like any other
+                Some(update_thread) if control.paused && control.view_back == 0.0 => {
+                    update_thread.send(Command::Step)
+                }
+                _ => sim.step_forward(),
+            }
+        }
+    }
+}
+
+fn draw_replay_status(sim: &Simulation) {
+    const SIZE: f32 = 30.0;
+    let x = screen_width() - 200.0;
+    if let Some(recording) = &sim.recording {
+        draw_text(&format!("REC {}", recording.ticks.len()), x, SIZE, SIZE, RED);
+    }
+    if let Some(playback) = &sim.playback {
+        let status = format!("REPLAY {}/{}", playback.index, playback.recording.ticks.len());
+        draw_text(&status, x, SIZE * 2.0, SIZE, GREEN);
+    }
+}
 
-    if is_key_down(KeyCode::Space) {
-        player.movement = Movement::default();
-        player.movement.pos = center;
+/// g to toggle prediction, n to cycle through pretend network conditions.
+fn handle_client_keys(sim: &mut Simulation, client: &mut Client, prediction: &mut Prediction) {
+    if is_key_pressed(KeyCode::N) {
+        client.set_conditions(client.up.settings.next_preset());
+    }
+    if is_key_pressed(KeyCode::G) {
+        prediction.enabled = !prediction.enabled;
+        prediction.clear();
+        // predicted ticks are numbered by the client, go back to the server's numbers
+        sim.game.tick_number = client.last_snapshot;
+        sim.received.clear();
     }
+}
 
-    player.movement.step(
-        Player::max_speed(speed_factor),
-        Player::base_friction(speed_factor),
-        Player::scaling_friction(speed_factor),
-    );
+fn draw_client_status(sim: &Simulation, client: &Client, prediction: &Prediction) {
+    const SIZE: f32 = 30.0;
+    let x = screen_width() - 200.0;
+    let missing = sim.received.missing();
+    let status = format!("ONLINE {}, lost {}", client.last_snapshot, missing);
+    draw_text(&status, x, SIZE * 3.0, SIZE, SKYBLUE);
+    if !client.up.settings.is_off() {
+        let status = format!(
+            "{}, dropped {}/{}",
+            client.up.settings, client.up.dropped, client.down.dropped
+        );
+        draw_text(&status, x - 400.0, SIZE * 3.0 + 40.0, 20.0, ORANGE);
+    }
+    if prediction.enabled {
+        let status = format!(
+            "predicting {} ticks, {} mispredicted, last off by {:.2}",
+            prediction.pending(),
+            prediction.mispredictions,
+            prediction.last_error
+        );
+        draw_text(&status, x - 200.0, SIZE * 3.0 + 20.0, 20.0, SKYBLUE);
+    }
 }
 
 // global state only needed for debug stuff rn
-fn draw(game: &GameState, global_state: &GlobalState, t: f32) {
-    let current = game.current_tick();
-    let prev = game.prev_tick();
+/// `player_pos` is where to draw the player, interpolated or extrapolated.
+/// `ticks_back` is how many ticks before the current tick are shown, see [`GameState::view`].
+fn draw(sim: &Simulation, ticks_back: f32, player_pos: Vec2) {
+    let (game, global_state, world) = (&sim.game, &sim.global, sim.world());
+    let View { t, before, prev, next: current, .. } = game.view(ticks_back);
+    let interp_strategy = global_state.interp_strategy;
     clear_background(GRAY);
-    current.player.draw(&prev.player, t);
+    world.draw();
+    let player_pos = world.constrain(prev.player().movement.pos, player_pos, Player::PLAYER_SIZE);
+    for (id, entity) in current.entities.iter() {
+        match prev.entities.get(id) {
+            _ if id == EntityId::PLAYER => entity.draw_at(player_pos),
+            // only interpolate if it was there the tick before too
+            Some(prev) => {
+                let before = before.and_then(|before| before.entities.get(id));
+                entity.draw(prev, before, t, interp_strategy, world)
+            }
+            // just spawned, projectiles start where they were fired
+            None => match entity.spawn_pos(t) {
+                Some(pos) => entity.draw_at(pos),
+                None => entity,
+            },
+        };
+    }
 
     if !global_state.dbg_hide_interp_info {
-        current.player.draw_dbg_prev(&prev.player, t);
-        current.player.draw_dbg(&prev.player, t);
+        for (id, entity) in current.entities.iter() {
+            if let Some(prev) = prev.entities.get(id) {
+                entity.draw_dbg_prev(prev, t);
+                entity.draw_dbg(prev, t);
+            }
+        }
 
         let realtime_wish_dir = global_state.avg_strategy.average(&global_state.input_buffer);
 
-        let interped_pos = prev.player.movement.interp(&current.player.movement, t);
-        dbg_arrow(interped_pos, realtime_wish_dir * 50.0, DBG_INTERP);
-        dbg_arrow(interped_pos, current.player.movement.accel, DBG_PREV);
-        dbg_arrow(interped_pos, current.player.movement.vel, DBG_NOW);
+        dbg_arrow(player_pos, realtime_wish_dir * 50.0, DBG_INTERP);
+        dbg_arrow(player_pos, current.player().movement.accel, DBG_PREV);
+        dbg_arrow(player_pos, current.player().movement.vel, DBG_NOW);
     }
 
-    // FIXME: increasing tps during runtime past initial value crashes
     // dbg tick buffer
     if global_state.dbg_buffer {
-        for i in (0..global_state.tick_settings.buffer_len - 1).rev() {
-            let buffer = &game.buffer;
-            let prev = &buffer.get_back(i + 1).unwrap().player;
-            let next = &buffer.get_back(i).unwrap().player;
-            next.draw(prev, t);
+        for back in (0..game.buffer().len()).rev() {
+            let (Ok(next), Ok(prev)) = (game.get_prev_tick(back), game.get_prev_tick(back + 1))
+            else {
+                continue;
+            };
+            let before = game.get_prev_tick(back + 2).ok();
+            for (id, next) in next.entities.iter() {
+                let Some(prev) = prev.entities.get(id) else {
+                    continue;
+                };
+                let before = before.and_then(|before| before.entities.get(id));
+                next.draw(prev, before, t, interp_strategy, world);
+            }
         }
     }
 
     dbg::dbg_info(game, global_state, t);
 }
-
-fn handle_inputs(input_buffer: &mut Vec<Vec2>) {
-    // TODO: possibly limit to like 10,000 or 80,000 or smth
-    // incase lag or mega input
-    input_buffer.push(Player::desired_dir());
-}
@@ -0,0 +1,236 @@
+//! Measuring how good an [`InterpolationStrategy`] is.
+//!
+//! The same input is run through [`Movement::step`] twice, once at the game's tps and once
+//! at a much higher reference tps that is close enough to the "real" continuous movement.
+//! Each interpolator is sampled many times between every game tick and compared against
+//! the reference run at the same point in time, after moving the reference so it goes
+//! exactly through both game ticks.
+
+use std::fmt::Display;
+
+use macroquad::math::Vec2;
+
+use crate::lerp_precise2;
+use crate::movement::{InterpolationStrategy, Movement};
+use crate::player::Player;
+use crate::state::TickSettings;
+
+/// How the interpolation was measured.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricsSettings {
+    /// Game ticks per second.
+    pub tps: f32,
+    /// Reference ticks per game tick, the reference runs at `tps * substeps`.
+    pub substeps: usize,
+    /// How many game ticks to run.
+    pub ticks: usize,
+    /// How many points to sample between two game ticks.
+    pub samples_per_tick: usize,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        Self { tps: 30.0, substeps: 16, ticks: 300, samples_per_tick: 16 }
+    }
+}
+
+/// Results of measuring an interpolator. Distances are in pixels.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct InterpMetrics {
+    pub strategy: InterpolationStrategy,
+    /// Biggest distance from the reference.
+    pub max_error: f32,
+    /// Root mean square distance from the reference.
+    pub rms_error: f32,
+    /// Furthest outside of the box the reference and both ticks were in during a tick.
+    pub max_overshoot: f32,
+    /// Biggest teleport from the end of one tick to the start of the next.
+    pub max_jump: f32,
+    /// Biggest sudden change of velocity across ticks, pixels per tick.
+    /// Only about accurate to 0.1 because of floats.
+    pub max_kink: f32,
+}
+
+impl Display for InterpMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{: <20} max: {:8.4}, rms: {:8.4}, overshoot: {:8.4}, jump: {:8.4}, kink: {:8.4}",
+            self.strategy.to_string(),
+            self.max_error,
+            self.rms_error,
+            self.max_overshoot,
+            self.max_jump,
+            self.max_kink
+        )
+    }
+}
+
+/// Every tick of a run, including the starting one.
+fn run(tps: f32, ticks: usize, substeps: usize, input: &impl Fn(f32) -> Vec2) -> Vec<Movement> {
+    // NOTE:PANIC: only with silly tps
+    let speed_factor = TickSettings::new(tps).unwrap().speed_factor;
+    let tick_len = tps.recip();
+
+    let mut movement = Movement::default();
+    let mut history = vec![movement.clone()];
+    for tick in 0..ticks {
+        // average the input of all the reference ticks so both runs get the same input
+        let start = tick * substeps;
+        let fine_len = tick_len / substeps as f32;
+        let wish_dir = (start..start + substeps)
+            .map(|fine_tick| input(fine_tick as f32 * fine_len))
+            .fold(Vec2::ZERO, |sum, dir| sum + dir)
+            / substeps as f32;
+
+        movement.accel = wish_dir * Player::accel(speed_factor);
+        movement.step(
+            Player::max_speed(speed_factor),
+            Player::base_friction(speed_factor),
+            Player::scaling_friction(speed_factor),
+        );
+        history.push(movement.clone());
+    }
+    history
+}
+
+/// Measure every strategy in `strategies` with the same input.
+/// `input` is the wish direction at a time in seconds.
+pub fn measure(
+    settings: &MetricsSettings, strategies: &[InterpolationStrategy], input: impl Fn(f32) -> Vec2,
+) -> Vec<InterpMetrics> {
+    let MetricsSettings { tps, substeps, ticks, samples_per_tick } = *settings;
+    let reference_tps = tps * substeps as f32;
+    let game = run(tps, ticks, substeps, &input);
+    let reference = run(reference_tps, ticks * substeps, 1, &input);
+    // `step()` adds the new vel to pos, so every tick ends up about half a tick ahead of
+    // the continuous movement. Shift the reference by the difference so only the
+    // interpolation error is left, not the error of the tick rate itself.
+    let offset = (1.0 - (substeps as f32).recip()) * 0.5;
+    // reference position `ticks` game ticks in, linear is fine because its so fine
+    let reference_at = |ticks: f32| {
+        let fine = (ticks + offset) * substeps as f32;
+        let i = (fine as usize).min(reference.len() - 2);
+        let t = fine - i as f32;
+        reference[i].interp0(&reference[i + 1], t)
+    };
+    // The two runs slowly drift apart, so pin the reference onto both ticks.
+    // What's left is the shape of the movement between ticks, the part interpolation guesses.
+    let pinned_reference_at = |tick: usize, t: f32| {
+        let start_drift = game[tick - 1].pos - reference_at((tick - 1) as f32);
+        let end_drift = game[tick].pos - reference_at(tick as f32);
+        reference_at((tick - 1) as f32 + t) + lerp_precise2(start_drift, end_drift, t)
+    };
+
+    strategies
+        .iter()
+        .map(|&strategy| {
+            let interp = |tick: usize, t: f32| {
+                let before = tick.checked_sub(2).map(|i| &game[i]);
+                game[tick - 1].interp_before(&game[tick], before, t, strategy)
+            };
+
+            let mut metrics = InterpMetrics { strategy, ..Default::default() };
+            let mut error_sum = 0.0;
+            let mut n = 0;
+            // skip the first 2 so every strategy has a tick before
+            for tick in 2..game.len() {
+                // anything outside of where the reference and both ticks were is overshooting
+                let mut min = game[tick - 1].pos.min(game[tick].pos);
+                let mut max = game[tick - 1].pos.max(game[tick].pos);
+                for i in 0..=samples_per_tick {
+                    let reference = pinned_reference_at(tick, i as f32 / samples_per_tick as f32);
+                    min = min.min(reference);
+                    max = max.max(reference);
+                }
+
+                for i in 0..=samples_per_tick {
+                    let t = i as f32 / samples_per_tick as f32;
+                    let pos = interp(tick, t);
+                    let error = pos.distance(pinned_reference_at(tick, t));
+                    metrics.max_error = metrics.max_error.max(error);
+                    error_sum += error * error;
+                    n += 1;
+
+                    let overshoot = (pos - pos.clamp(min, max)).length();
+                    metrics.max_overshoot = metrics.max_overshoot.max(overshoot);
+                }
+
+                if tick + 1 < game.len() {
+                    const H: f32 = 1e-3;
+                    let jump = interp(tick, 1.0).distance(interp(tick + 1, 0.0));
+                    metrics.max_jump = metrics.max_jump.max(jump);
+
+                    let end_slope = (interp(tick, 1.0) - interp(tick, 1.0 - H)) / H;
+                    let start_slope = (interp(tick + 1, H) - interp(tick + 1, 0.0)) / H;
+                    metrics.max_kink = metrics.max_kink.max(end_slope.distance(start_slope));
+                }
+            }
+            metrics.rms_error = (error_sum / n.max(1) as f32).sqrt();
+            metrics
+        })
+        .collect()
+}
+
+/// Every [`InterpolationStrategy`].
+pub const ALL_STRATEGIES: [InterpolationStrategy; 6] = [
+    InterpolationStrategy::Linear,
+    InterpolationStrategy::ConstantVelocity,
+    InterpolationStrategy::ConstantAcceleration,
+    InterpolationStrategy::Curvy,
+    InterpolationStrategy::Hermite,
+    InterpolationStrategy::CatmullRom,
+];
+
+/// Circling, stopping and turning around, a bit of everything.
+pub fn wiggly_input(secs: f32) -> Vec2 {
+    match (secs * 2.0) as usize % 4 {
+        0 => Vec2::from_angle(secs * 4.0),
+        1 => Vec2::ZERO,
+        2 => Vec2::X,
+        _ => -Vec2::X,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metrics(strategy: InterpolationStrategy) -> InterpMetrics {
+        measure(&MetricsSettings::default(), &[strategy], wiggly_input).remove(0)
+    }
+
+    #[test]
+    #[ignore = "prints a report, run with `cargo test report -- --ignored --nocapture`"]
+    fn report() {
+        for tps in [10.0, 30.0, 60.0] {
+            let settings = MetricsSettings { tps, ..Default::default() };
+            println!("{} tps:", tps);
+            for metrics in measure(&settings, &ALL_STRATEGIES, wiggly_input) {
+                println!("  {}", metrics);
+            }
+        }
+    }
+
+    #[test]
+    fn splines_never_jump_or_kink() {
+        for strategy in [InterpolationStrategy::Hermite, InterpolationStrategy::CatmullRom] {
+            let metrics = metrics(strategy);
+            assert_eq!(metrics.max_jump, 0.0, "{}", metrics);
+            assert!(metrics.max_kink < 0.1, "{}", metrics);
+        }
+    }
+
+    // regressions, numbers are a little above what they were when written (30 tps)
+    #[test]
+    fn no_worse_than_before() {
+        let linear = metrics(InterpolationStrategy::Linear);
+        let curvy = metrics(InterpolationStrategy::Curvy);
+        let hermite = metrics(InterpolationStrategy::Hermite);
+        let catmull_rom = metrics(InterpolationStrategy::CatmullRom);
+        assert!(linear.rms_error < 0.45 && linear.max_jump == 0.0, "{}", linear);
+        assert!(curvy.rms_error < 0.16 && curvy.max_overshoot < 0.25, "{}", curvy);
+        assert!(hermite.rms_error < 0.25 && hermite.max_overshoot < 0.6, "{}", hermite);
+        assert!(catmull_rom.rms_error < 0.25 && catmull_rom.max_overshoot < 0.2, "{}", catmull_rom);
+    }
+}
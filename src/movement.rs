@@ -2,9 +2,44 @@
 
 // use crate::spline;
 // use macroquad::color::*;
+use std::fmt::Display;
+
 use macroquad::math::Vec2;
 
-use super::lerp_fast2;
+use super::{hermite2, lerp_fast2};
+use crate::world::World;
+
+/// How to draw the player between the previous and current tick.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InterpolationStrategy {
+    /// Straight line between positions. See [`Movement::interp0`].
+    Linear,
+    /// Assume constant velocity, straight worse than linear. See [`Movement::interp1`].
+    ConstantVelocity,
+    /// Assume constant acceleration. See [`Movement::interp2`].
+    ConstantAcceleration,
+    /// Best one so far. See [`Movement::interp3`].
+    #[default]
+    Curvy,
+    /// Cubic spline through both ticks using their velocities. See [`Movement::hermite`].
+    Hermite,
+    /// Cubic spline with better tangents from the tick before too.
+    /// Same as `Hermite` if there is no tick before. See [`Movement::catmull_rom`].
+    CatmullRom,
+}
+
+impl Display for InterpolationStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpolationStrategy::Linear => write!(f, "Linear"),
+            InterpolationStrategy::ConstantVelocity => write!(f, "ConstantVelocity"),
+            InterpolationStrategy::ConstantAcceleration => write!(f, "ConstantAcceleration"),
+            InterpolationStrategy::Curvy => write!(f, "Curvy"),
+            InterpolationStrategy::Hermite => write!(f, "Hermite"),
+            InterpolationStrategy::CatmullRom => write!(f, "CatmullRom"),
+        }
+    }
+}
 
 // "3" types of friction
 // static // ignore
@@ -37,6 +72,23 @@ impl Movement {
             .step_frictionless(max_speed)
     }
 
+    /// Like [`Movement::step`], but as a circle of `radius` that bumps into `world` and
+    /// slides along it instead of going through.
+    pub fn step_in(
+        &mut self, world: &World, radius: f32, max_speed: f32, base_friction: f32,
+        scaling_friction: f32,
+    ) -> &mut Self {
+        self.apply_friction(base_friction, scaling_friction)
+            .step_vel()
+            .limit_speed(max_speed);
+        let Movement { pos, vel, .. } = self;
+        *pos = world.slide(*pos, *vel, radius, |normal| {
+            // lose the speed going into the wall, keep the rest
+            *vel -= normal * vel.dot(normal).min(0.0);
+        });
+        self
+    }
+
     /// Update player velocity and position
     pub fn step_frictionless(&mut self, max_speed: f32) -> &mut Self {
         self.step_vel().limit_speed(max_speed).step_pos();
@@ -73,9 +125,40 @@ impl Movement {
         self
     }
 
-    pub fn interp(&self, next: &Self, t: f32) -> Vec2 {
-        // self.interp0(next, t) // linear
-        self.interp3(next, t) // curvy
+    /// Guess where it will be `ticks` ticks in the future, assuming accel doesn't change.
+    /// Steps a copy forward with friction like normal, interpolating between the last 2 steps.
+    pub fn extrapolate(
+        &self, ticks: f32, max_speed: f32, base_friction: f32, scaling_friction: f32,
+        strategy: InterpolationStrategy,
+    ) -> Vec2 {
+        let mut prev = self.clone();
+        for _ in 0..ticks.max(0.0) as usize {
+            prev.step(max_speed, base_friction, scaling_friction);
+        }
+        let mut next = prev.clone();
+        next.step(max_speed, base_friction, scaling_friction);
+        prev.interp(&next, ticks.max(0.0).fract(), strategy)
+    }
+
+    pub fn interp(&self, next: &Self, t: f32, strategy: InterpolationStrategy) -> Vec2 {
+        self.interp_before(next, None, t, strategy)
+    }
+
+    /// Interp between `self` and `next`, `before` is the tick before `self` if there is one.
+    pub fn interp_before(
+        &self, next: &Self, before: Option<&Self>, t: f32, strategy: InterpolationStrategy,
+    ) -> Vec2 {
+        match strategy {
+            InterpolationStrategy::Linear => self.interp0(next, t),
+            InterpolationStrategy::ConstantVelocity => self.interp1(next, t),
+            InterpolationStrategy::ConstantAcceleration => self.interp2(next, t),
+            InterpolationStrategy::Curvy => self.interp3(next, t),
+            InterpolationStrategy::Hermite => self.hermite(next, t),
+            InterpolationStrategy::CatmullRom => match before {
+                Some(before) => self.catmull_rom(next, before, t),
+                None => self.hermite(next, t),
+            },
+        }
 
         // horrible:
         // self.interp3_c(next, t)
@@ -160,7 +243,7 @@ impl Movement {
     // xf = xo + v0*t + 1/2*a*t^2
     /// interp assume constant velocity
     /// NOTE: staight worse than interp0
-    fn interp1(&self, next: &Self, t: f32) -> Vec2 {
+    pub fn interp1(&self, next: &Self, t: f32) -> Vec2 {
         self.pos + next.vel * t
     }
 
@@ -173,6 +256,8 @@ impl Movement {
     /// I dont know how this works.
     /// I've tried for like 3 days of studying kinematics, calculas and splines
     /// and fiddling around, this is the best
+    ///
+    /// Lowest rms error of them all, see [`crate::metrics`] for numbers.
     pub fn interp3(&self, next: &Self, t: f32) -> Vec2 {
         // let lerp_dumb = |a, b, t| if t <= 0.5 {a} else {b};
         let t_2 = t * t;
@@ -190,6 +275,30 @@ impl Movement {
         // self.interp2(next, t)
     }
 
+    // Every tick does `pos += vel`, so `vel` is exactly how far it moved during the tick,
+    // in units per tick. A tick is also the whole 0..=1 of `t` so `vel` is already a
+    // correctly scaled tangent, no multiplying by tick length needed.
+    // Tangents only use the tick they're at and older ticks, so the end tangent of one
+    // tick is the same as the start tangent of the next one -> C1 continuous.
+
+    /// Cubic Hermite spline, tangents are the velocity of each tick.
+    /// Always hits both ticks and is smooth across ticks.
+    /// Lags a bit on curves because `vel` is the average over the last tick,
+    /// not the velocity right at the tick.
+    pub fn hermite(&self, next: &Self, t: f32) -> Vec2 {
+        hermite2(self.pos, self.vel, next.pos, next.vel, t)
+    }
+
+    /// Cubic Hermite spline, tangents estimated from the tick and the two before it
+    /// (second order backward difference, `(3 * v_n - v_n-1) / 2`). Real Catmull-Rom
+    /// would need the next tick which doesn't exist yet.
+    /// Always hits both ticks and is smooth across ticks.
+    pub fn catmull_rom(&self, next: &Self, before: &Self, t: f32) -> Vec2 {
+        let m0 = (3.0 * self.vel - before.vel) * 0.5;
+        let m1 = (3.0 * next.vel - self.vel) * 0.5;
+        hermite2(self.pos, m0, next.pos, m1, t)
+    }
+
     // fn interp3_c(&self, next: &Self, t: f32) -> Vec2 {
     //     // let lerp_dumb = |a, b, t| if t <= 0.5 {a} else {b};
     //     let t_2 = t * t;
@@ -236,3 +345,43 @@ impl Movement {
     //     // self.interp3(next, t)
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Movement every tick, turning and speeding up and slowing down.
+    fn ticks(n: usize) -> Vec<Movement> {
+        let mut movement = Movement::default();
+        (0..n)
+            .map(|i| {
+                movement.accel =
+                    Vec2::from_angle(i as f32 * 0.3) * if i % 20 < 10 { 2.5 } else { 0.0 };
+                movement.step(15.0, 0.5, 5e-2);
+                movement.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splines_hit_ticks_and_are_c1() {
+        const H: f32 = 1e-3;
+        let ticks = ticks(100);
+        for strategy in [InterpolationStrategy::Hermite, InterpolationStrategy::CatmullRom] {
+            let interp = |i: usize, t: f32| {
+                let before = i.checked_sub(1).map(|i| &ticks[i]);
+                ticks[i].interp_before(&ticks[i + 1], before, t, strategy)
+            };
+
+            for i in 1..ticks.len() - 2 {
+                assert_eq!(interp(i, 0.0), ticks[i].pos, "{}", strategy);
+                assert_eq!(interp(i, 1.0), ticks[i + 1].pos, "{}", strategy);
+
+                let end_slope = (interp(i, 1.0) - interp(i, 1.0 - H)) / H;
+                let start_slope = (interp(i + 1, H) - interp(i + 1, 0.0)) / H;
+                let diff = (end_slope - start_slope).length();
+                assert!(diff < 0.1, "{} tick {}: {} vs {}", strategy, i, end_slope, start_slope);
+            }
+        }
+    }
+}
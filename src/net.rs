@@ -0,0 +1,633 @@
+//! Client/server over UDP. The server runs the only real [`Simulation`], clients send it
+//! their averaged input every tick and draw the snapshots that come back.
+//!
+//! # Packet format
+//! One message per datagram, little endian like recordings.
+//! ```text
+//! magic "ITNT", version u16, kind u8, then
+//! kind 1, input:    sequence u64, ack u64, flags u8 (1 = reset, 2 = fire follows),
+//!                   wish dir 2 x f32, [fire target 2 x f32, subtick u8]
+//! kind 2, snapshot: tick number u64, last input sequence u64, TickSettings,
+//!                   spawn 2 x f32, baseline u8, tick state (see codec)
+//! ```
+//! The input sequence counts up by 1 every client tick, so the client knows which of
+//! its inputs a snapshot already includes.
+//!
+//! Ack is the newest tick the client received. The server encodes snapshots as the
+//! difference to that tick if it still has it, baseline is how many ticks older it is.
+//! Baseline 0 is a full snapshot.
+//!
+//! Spawn is sent so clients with a different window size still place NPCs and walls
+//! the same as the server, or every predicted tick would be wrong.
+//!
+//! Only the same version can talk to each other. Version 2 snapshots were only the
+//! player until entities got ids, and that changed without a bump, so builds from
+//! before version 3 may not understand each other even on the same version.
+
+pub mod codec;
+pub mod conditioner;
+
+pub use conditioner::{Conditioner, ConditionerSettings};
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Instant;
+
+use macroquad::math::Vec2;
+
+use crate::replay::{
+    invalid, read_bytes, read_fire, read_settings, read_vec2, write_fire, write_settings,
+    write_vec2,
+};
+use crate::state::tick_buffer::TickBuffer;
+use crate::state::{Simulation, TickInput, TickNumber, TickSettings, TickState};
+
+const MAGIC: &[u8; 4] = b"ITNT";
+const VERSION: u16 = 4;
+
+const KIND_INPUT: u8 = 1;
+const KIND_SNAPSHOT: u8 = 2;
+
+const FLAG_RESET: u8 = 1;
+const FLAG_FIRE: u8 = 2;
+
+/// Fits in 1 datagram on about any network without getting split up. Bigger packets
+/// can't be encoded, which leaves room for around 70 projectiles in a full snapshot.
+const MAX_PACKET_LEN: usize = 1200;
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// One tick of input from a client.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct InputPacket {
+    pub sequence: usize,
+    /// Newest tick number received, 0 if none.
+    pub ack: TickNumber,
+    pub input: TickInput,
+}
+
+/// The server's state after a tick.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Snapshot {
+    pub tick_number: TickNumber,
+    /// Sequence of the newest input the server had used by this tick.
+    pub last_input: usize,
+    pub settings: TickSettings,
+    pub spawn: Vec2,
+    pub state: TickState,
+}
+
+impl Snapshot {
+    /// The current tick of `sim`.
+    pub fn new(sim: &Simulation, last_input: usize) -> Self {
+        Self {
+            tick_number: sim.game.tick_number,
+            last_input,
+            settings: sim.global.tick_settings.clone(),
+            spawn: sim.spawn,
+            state: sim.game.current_tick().clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packet {
+    Input(InputPacket),
+    Snapshot(Snapshot),
+}
+
+impl Packet {
+    pub fn encode(&self) -> io::Result<Vec<u8>> {
+        self.encode_against(None)
+    }
+
+    /// Encode a snapshot as the difference to `baseline`, a (tick number, state) the other
+    /// side already has. Full snapshot if `None` or the baseline is too old.
+    /// Fails if it ends up longer than `MAX_PACKET_LEN`, 1200 bytes.
+    pub fn encode_against(
+        &self, baseline: Option<(TickNumber, &TickState)>,
+    ) -> io::Result<Vec<u8>> {
+        let mut w = Vec::with_capacity(MAX_PACKET_LEN);
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        match self {
+            Packet::Input(packet) => {
+                w.write_all(&[KIND_INPUT])?;
+                w.write_all(&(packet.sequence as u64).to_le_bytes())?;
+                w.write_all(&(packet.ack.0 as u64).to_le_bytes())?;
+                let mut flags = 0;
+                if packet.input.reset {
+                    flags |= FLAG_RESET;
+                }
+                if packet.input.fire.is_some() {
+                    flags |= FLAG_FIRE;
+                }
+                w.write_all(&[flags])?;
+                write_vec2(&mut w, packet.input.wish_dir)?;
+                if let Some(fire) = &packet.input.fire {
+                    write_fire(&mut w, fire)?;
+                }
+            }
+            Packet::Snapshot(snapshot) => {
+                w.write_all(&[KIND_SNAPSHOT])?;
+                w.write_all(&(snapshot.tick_number.0 as u64).to_le_bytes())?;
+                w.write_all(&(snapshot.last_input as u64).to_le_bytes())?;
+                write_settings(&mut w, &snapshot.settings)?;
+                write_vec2(&mut w, snapshot.spawn)?;
+
+                let baseline = baseline.and_then(|(tick_number, state)| {
+                    let back = snapshot.tick_number.since(tick_number)?;
+                    Some((u8::try_from(back).ok().filter(|back| *back > 0)?, state))
+                });
+                w.write_all(&[baseline.map_or(0, |(back, _)| back)])?;
+                codec::write_state(&mut w, &snapshot.state, baseline.map(|(_, state)| state))?;
+            }
+        }
+        if w.len() > MAX_PACKET_LEN {
+            return Err(invalid(&format!("packet too big: {} bytes", w.len())));
+        }
+        Ok(w)
+    }
+
+    pub fn decode(r: &[u8]) -> io::Result<Self> {
+        Self::decode_against(r, |_| None)
+    }
+
+    /// `baseline` looks up a tick by tick number for snapshots that need one.
+    pub fn decode_against<'a>(
+        mut r: &[u8], baseline: impl FnOnce(TickNumber) -> Option<&'a TickState>,
+    ) -> io::Result<Self> {
+        let r = &mut r;
+        let magic: [u8; 4] = read_bytes(r)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a packet"));
+        }
+        let version = u16::from_le_bytes(read_bytes(r)?);
+        if version != VERSION {
+            return Err(invalid("unsupported packet version"));
+        }
+
+        let [kind] = read_bytes(r)?;
+        let packet = match kind {
+            KIND_INPUT => {
+                let sequence = u64::from_le_bytes(read_bytes(r)?) as usize;
+                let ack = TickNumber(u64::from_le_bytes(read_bytes(r)?) as usize);
+                let [flags] = read_bytes(r)?;
+                let wish_dir = read_vec2(r)?;
+                if !wish_dir.is_finite() || wish_dir.length() > 1.0 + 1e-3 {
+                    return Err(invalid("bad wish dir"));
+                }
+                let fire = if flags & FLAG_FIRE != 0 { Some(read_fire(r)?) } else { None };
+                let input = TickInput { wish_dir, reset: flags & FLAG_RESET != 0, fire };
+                Packet::Input(InputPacket { sequence, ack, input })
+            }
+            KIND_SNAPSHOT => {
+                let tick_number = TickNumber(u64::from_le_bytes(read_bytes(r)?) as usize);
+                let last_input = u64::from_le_bytes(read_bytes(r)?) as usize;
+                let settings = read_settings(r)?;
+                let spawn = read_vec2(r)?;
+                if !spawn.is_finite() {
+                    return Err(invalid("bad spawn"));
+                }
+
+                let [back] = read_bytes(r)?;
+                let baseline = match back {
+                    0 => None,
+                    back => {
+                        let tick_number = tick_number
+                            .checked_sub(back as usize)
+                            .ok_or_else(|| invalid("baseline before tick 0"))?;
+                        Some(baseline(tick_number).ok_or_else(|| invalid("missing baseline"))?)
+                    }
+                };
+                let state = codec::read_state(r, baseline)?;
+                Packet::Snapshot(Snapshot { tick_number, last_input, settings, spawn, state })
+            }
+            _ => return Err(invalid("unknown packet kind")),
+        };
+        if !r.is_empty() {
+            return Err(invalid("trailing bytes"));
+        }
+        Ok(packet)
+    }
+}
+
+/// Receive every packet waiting on a nonblocking socket, still encoded.
+/// Anything longer than [`MAX_PACKET_LEN`] gets cut off and fails to decode.
+fn receive_all(socket: &UdpSocket) -> io::Result<Vec<(Vec<u8>, SocketAddr)>> {
+    let mut packets = Vec::new();
+    let mut buf = [0; MAX_PACKET_LEN];
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((len, addr)) => packets.push((buf[..len].to_vec(), addr)),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(packets),
+            // windows reports the other side not listening on the next receive
+            Err(err) if err.kind() == io::ErrorKind::ConnectionReset => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Runs the game for one client, sending a snapshot back after every tick.
+#[derive(Debug)]
+pub struct Server {
+    socket: UdpSocket,
+    pub sim: Simulation,
+    /// Who to send snapshots to. Others are ignored until it times out.
+    pub client: Option<SocketAddr>,
+    /// `sim.time` when the client was last heard from.
+    last_heard: f64,
+    /// Input received but not used by a tick yet, oldest first.
+    inputs: VecDeque<InputPacket>,
+    /// Sequence of the newest input used by a tick.
+    pub last_input: usize,
+    /// Newest tick the client said it received, snapshots are encoded against it.
+    pub acked: TickNumber,
+    pub full_snapshots: usize,
+    pub bytes_sent: usize,
+}
+
+impl Server {
+    /// More queued input than this and the client's clock is running ahead,
+    /// the extra input gets averaged into 1 tick to catch up.
+    pub const MAX_QUEUED_INPUTS: usize = 4;
+    /// Seconds without input until someone else can take over.
+    pub const CLIENT_TIMEOUT_SECS: f64 = 5.0;
+
+    pub fn bind(addr: impl ToSocketAddrs, sim: Simulation) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            sim,
+            client: None,
+            last_heard: 0.0,
+            inputs: VecDeque::new(),
+            last_input: 0,
+            acked: TickNumber::default(),
+            full_snapshots: 0,
+            bytes_sent: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Queue all received input. Late input, older than what's already queued or used,
+    /// is dropped. A new client address starts over, once the old one timed out.
+    pub fn receive(&mut self) -> io::Result<()> {
+        for (bytes, addr) in receive_all(&self.socket)? {
+            let Ok(Packet::Input(packet)) = Packet::decode(&bytes) else {
+                continue;
+            };
+            if self.client != Some(addr) {
+                let timed_out = self.sim.time - self.last_heard > Self::CLIENT_TIMEOUT_SECS;
+                if self.client.is_some() && !timed_out {
+                    continue;
+                }
+                self.client = Some(addr);
+                self.inputs.clear();
+                self.last_input = packet.sequence.saturating_sub(1);
+                self.acked = TickNumber::default();
+            }
+            self.last_heard = self.sim.time;
+            self.acked = self.acked.max(packet.ack);
+            let newest = self.inputs.back().map_or(self.last_input, |input| input.sequence);
+            if packet.sequence > newest {
+                self.inputs.push_back(packet);
+            }
+        }
+        Ok(())
+    }
+
+    /// Advance the clock, run all the ticks due and send a snapshot after each.
+    /// Every tick uses 1 queued input so the client can predict exactly what happens.
+    /// The input still goes through the same averaging as local input, the first shot
+    /// is kept as the client fired it.
+    /// Returns how many ticks were stepped. Ticks still run if sending a snapshot fails,
+    /// the last error is returned after.
+    pub fn update(&mut self, delta_time: f32) -> io::Result<usize> {
+        let ticks_due = self.sim.advance_clock(delta_time);
+        self.receive()?;
+        let mut result = Ok(ticks_due);
+        for _ in 0..ticks_due {
+            let take = self.inputs.len().saturating_sub(Self::MAX_QUEUED_INPUTS).max(1);
+            let mut reset = false;
+            let mut fire = None;
+            for packet in self.inputs.drain(..take.min(self.inputs.len())) {
+                self.last_input = packet.sequence;
+                reset |= packet.input.reset;
+                fire = fire.or(packet.input.fire);
+                self.sim.poll_input(&mut |_| packet.input.wish_dir);
+            }
+            let input = TickInput { fire, ..self.sim.take_input(reset) };
+            self.sim.tick_with(input);
+            if let Err(err) = self.send_snapshot() {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    fn send_snapshot(&mut self) -> io::Result<()> {
+        let Some(client) = self.client else {
+            return Ok(());
+        };
+        let snapshot = Snapshot::new(&self.sim, self.last_input);
+        // NOTE: only ticks still in the buffer can be a baseline
+        let baseline = Some(self.acked)
+            .filter(|acked| acked.0 > 0)
+            .and_then(|acked| Some((acked, self.sim.game.get_tick(acked).ok()?)));
+        if baseline.is_none() {
+            self.full_snapshots += 1;
+        }
+        let bytes = Packet::Snapshot(snapshot).encode_against(baseline)?;
+        match self.socket.send_to(&bytes, client) {
+            Ok(len) => {
+                self.bytes_sent += len;
+                Ok(())
+            }
+            // full send buffer, the next snapshot will do
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Talks to a [`Server`]. Packets both ways go through a [`Conditioner`] to test how bad
+/// networks look without needing one.
+#[derive(Debug)]
+pub struct Client {
+    socket: UdpSocket,
+    /// Sequence of the last input sent.
+    pub sequence: usize,
+    /// Tick number of the newest snapshot received.
+    pub last_snapshot: TickNumber,
+    start: Instant,
+    /// Client to server.
+    pub up: Conditioner<Vec<u8>>,
+    /// Server to client.
+    pub down: Conditioner<Vec<u8>>,
+    /// Received ticks for decoding snapshots encoded against them.
+    baselines: TickBuffer<TickState>,
+    /// Snapshots that couldn't be decoded, like when their baseline was lost.
+    pub undecodable: usize,
+}
+
+impl Client {
+    /// Snapshots can only be encoded against ticks up to 255 older.
+    const BASELINE_SPAN: usize = u8::MAX as usize;
+
+    pub fn connect(server: impl ToSocketAddrs) -> io::Result<Self> {
+        let server = server
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no server address"))?;
+        let local: SocketAddr =
+            if server.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0; 8], 0).into() };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(server)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            sequence: 0,
+            last_snapshot: TickNumber::default(),
+            start: Instant::now(),
+            up: Conditioner::new(ConditionerSettings::OFF, 1),
+            down: Conditioner::new(ConditionerSettings::OFF, 2),
+            baselines: TickBuffer::new(Self::BASELINE_SPAN),
+            undecodable: 0,
+        })
+    }
+
+    /// Pretend the network is this bad in both directions.
+    pub fn set_conditions(&mut self, settings: ConditionerSettings) {
+        self.up.settings = settings.clone();
+        self.down.settings = settings;
+    }
+
+    fn time(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+
+    /// Send everything the conditioner let through by now.
+    fn flush(&mut self) -> io::Result<()> {
+        for packet in self.up.receive(self.time()) {
+            match self.socket.send(&packet) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Send this tick's input, returns its sequence.
+    pub fn send_input(&mut self, input: TickInput) -> io::Result<usize> {
+        self.sequence += 1;
+        let ack = self.last_snapshot;
+        let packet = Packet::Input(InputPacket { sequence: self.sequence, ack, input });
+        self.up.send(packet.encode()?, self.time());
+        self.flush()?;
+        Ok(self.sequence)
+    }
+
+    /// All snapshots received since the last call, oldest first.
+    /// Can include snapshots older than ones received before, when they arrive out of order.
+    /// Also sends input held back by the conditioner, so call it every frame.
+    pub fn receive(&mut self) -> io::Result<Vec<Snapshot>> {
+        self.flush()?;
+        let time = self.time();
+        for (packet, _) in receive_all(&self.socket)? {
+            self.down.send(packet, time);
+        }
+        let mut snapshots = Vec::new();
+        for bytes in self.down.receive(time) {
+            match Packet::decode_against(&bytes, |tick_number| self.baselines.get(tick_number)) {
+                Ok(Packet::Snapshot(snapshot)) => {
+                    self.baselines.insert(snapshot.tick_number, snapshot.state.clone());
+                    snapshots.push(snapshot);
+                }
+                Ok(_) => {}
+                Err(_) => self.undecodable += 1,
+            }
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.tick_number);
+        snapshots.dedup_by_key(|snapshot| snapshot.tick_number);
+        if let Some(newest) = snapshots.last() {
+            self.last_snapshot = self.last_snapshot.max(newest.tick_number);
+        }
+        Ok(snapshots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use macroquad::math::Vec2;
+
+    use super::*;
+    use crate::entity::{Entity, EntityKind};
+    use crate::state::Fire;
+
+    #[test]
+    fn packet_round_trip() {
+        let fire = Some(Fire::new(Vec2::new(10.0, 20.0), 0.5));
+        let input = TickInput { wish_dir: Vec2::Y, reset: true, fire };
+        let input = InputPacket { sequence: 7, ack: TickNumber(40), input };
+        let mut state = TickState::default();
+        state.player_mut().movement.pos = Vec2::new(1.5, -2.0);
+        state.player_mut().movement.vel = Vec2::X;
+        let snapshot = Snapshot {
+            tick_number: TickNumber(42),
+            last_input: 7,
+            settings: TickSettings::default(),
+            spawn: Vec2::new(400.0, 300.0),
+            state,
+        };
+
+        for packet in [Packet::Input(input), Packet::Snapshot(snapshot)] {
+            let bytes = packet.encode().unwrap();
+            assert_eq!(Packet::decode(&bytes).unwrap(), packet);
+            assert!(Packet::decode(&bytes[..bytes.len() - 1]).is_err());
+        }
+        assert!(Packet::decode(b"ITRC\x01\x00\x01").is_err());
+    }
+
+    #[test]
+    fn delta_snapshots() {
+        let mut baseline = TickState::default();
+        baseline.player_mut().movement.pos = Vec2::new(400.0, 300.0);
+        let mut state = baseline.clone();
+        state.player_mut().movement.pos.x += 2.0;
+        state.player_mut().movement.vel.x = 2.0;
+        let snapshot = Packet::Snapshot(Snapshot {
+            tick_number: TickNumber(300),
+            last_input: 7,
+            settings: TickSettings::default(),
+            spawn: Vec2::new(400.0, 300.0),
+            state,
+        });
+
+        let full = snapshot.encode().unwrap();
+        let delta = snapshot.encode_against(Some((TickNumber(298), &baseline))).unwrap();
+        assert!(delta.len() < full.len());
+        assert_eq!(Packet::decode_against(&delta, |_| Some(&baseline)).unwrap(), snapshot);
+        assert!(Packet::decode(&delta).is_err());
+        // too old or not older at all, falls back to full
+        assert_eq!(snapshot.encode_against(Some((TickNumber(40), &baseline))).unwrap(), full);
+        assert_eq!(snapshot.encode_against(Some((TickNumber(300), &baseline))).unwrap(), full);
+    }
+
+    #[test]
+    fn client_fires() {
+        const DT: f32 = 1.0 / 30.0;
+        let sim = Simulation::new(30.0, Vec2::ZERO).unwrap();
+        let mut server = Server::bind("127.0.0.1:0", sim).unwrap();
+        let mut client = Client::connect(server.local_addr().unwrap()).unwrap();
+
+        let mut snapshots = Vec::new();
+        for i in 0..10 {
+            let fire = (i == 3).then(|| Fire::new(Vec2::new(0.0, -100.0), 0.5));
+            client.send_input(TickInput { wish_dir: Vec2::X, reset: false, fire }).unwrap();
+            sleep(Duration::from_millis(1));
+            server.update(DT).unwrap();
+            sleep(Duration::from_millis(1));
+            snapshots.extend(client.receive().unwrap());
+        }
+
+        let projectile = snapshots
+            .iter()
+            .flat_map(|snapshot| snapshot.state.entities.iter())
+            .find(|(_, entity)| matches!(entity.kind, EntityKind::Projectile { .. }));
+        let (_, projectile) = projectile.expect("no projectile in any snapshot");
+        // fired halfway into the tick, like the client said
+        let EntityKind::Projectile { subtick, .. } = projectile.kind else { unreachable!() };
+        assert_eq!(subtick, 128);
+        assert!(projectile.movement.vel.y < 0.0);
+    }
+
+    #[test]
+    fn big_snapshots() {
+        let mut state = TickState::default();
+        let tick_settings = TickSettings::default();
+        let mut snapshot = |projectiles| {
+            for i in state.entities.len()..projectiles {
+                let fire = Fire::new(Vec2::from_angle(i as f32), 0.5);
+                let projectile = Entity::projectile(Vec2::ZERO, Vec2::X, &fire, &tick_settings);
+                state.entities.spawn(projectile);
+            }
+            let spawn = Vec2::new(400.0, 300.0);
+            Packet::Snapshot(Snapshot { state: state.clone(), spawn, ..Default::default() })
+        };
+
+        let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+        b.set_nonblocking(true).unwrap();
+        let packet = snapshot(40);
+        let bytes = packet.encode().unwrap();
+        assert!(bytes.len() > 256, "{}", bytes.len());
+        a.send_to(&bytes, b.local_addr().unwrap()).unwrap();
+        sleep(Duration::from_millis(10));
+        let received = receive_all(&b).unwrap();
+        assert_eq!(received.len(), 1);
+        let Packet::Snapshot(decoded) = Packet::decode(&received[0].0).unwrap() else {
+            panic!("not a snapshot");
+        };
+        let Packet::Snapshot(sent) = packet else { unreachable!() };
+        assert!(codec::same_quantized(&decoded.state, &sent.state));
+
+        // way too many doesn't get sent cut off
+        let err = snapshot(200).encode().unwrap_err();
+        assert!(err.to_string().starts_with("packet too big"), "{}", err);
+    }
+
+    #[test]
+    fn localhost() {
+        const DT: f32 = 1.0 / 30.0;
+        let sim = Simulation::new(30.0, Vec2::ZERO).unwrap();
+        let mut server = Server::bind("127.0.0.1:0", sim).unwrap();
+        let mut client = Client::connect(server.local_addr().unwrap()).unwrap();
+        let mut intruder = Client::connect(server.local_addr().unwrap()).unwrap();
+
+        let mut snapshots = Vec::new();
+        for i in 0..100 {
+            client
+                .send_input(TickInput { wish_dir: Vec2::X, reset: false, ..Default::default() })
+                .unwrap();
+            if i % 10 == 5 {
+                intruder.send_input(TickInput::default()).unwrap();
+            }
+            // give the packets time to arrive
+            sleep(Duration::from_millis(1));
+            server.update(DT).unwrap();
+            sleep(Duration::from_millis(1));
+            snapshots.extend(client.receive().unwrap());
+        }
+
+        assert!(snapshots.len() > 50, "{}", snapshots.len());
+        assert!(snapshots.windows(2).all(|w| w[0].tick_number < w[1].tick_number));
+        let newest = snapshots.last().unwrap();
+        assert_eq!(newest.tick_number, server.sim.game.tick_number);
+        assert!(codec::same_quantized(&newest.state, server.sim.game.current_tick()));
+        assert!(newest.state.player().movement.pos.x > 0.0);
+        assert_eq!(newest.spawn, server.sim.spawn());
+        // the first client keeps the slot while it's sending
+        assert!(intruder.receive().unwrap().is_empty());
+
+        // until it goes quiet
+        let first = server.client;
+        server.update(Server::CLIENT_TIMEOUT_SECS as f32 + 1.0).unwrap();
+        intruder.send_input(TickInput::default()).unwrap();
+        sleep(Duration::from_millis(1));
+        server.update(DT).unwrap();
+        assert!(server.client.is_some() && server.client != first);
+        assert!(newest.last_input > 50);
+        // client acked them, so almost all are deltas
+        assert!(server.full_snapshots < 5, "{}", server.full_snapshots);
+    }
+}
@@ -0,0 +1,266 @@
+//! Compact encoding of a [`TickState`], either on its own or as the difference to an
+//! older tick the other side already has (a baseline).
+//!
+//! Positions, velocities and accelerations are quantized to fixed point, then every
+//! component is written as a zigzag varint, so small numbers and small changes are 1 byte.
+//! ```text
+//! next entity id varint, entity count varint, then per entity:
+//!     id varint, kind, pos x, y, vel x, y, accel x, y: 6 x zigzag varint
+//! kind: u8 (0 = player, 1 = npc, 2 = projectile), projectiles follow with
+//!     ticks left varint, subtick u8
+//! ```
+//! With a baseline every value is the difference to the same entity in the baseline,
+//! entities that weren't in the baseline are sent as is.
+
+use std::io::{self, Read, Write};
+
+use macroquad::math::Vec2;
+
+use crate::entity::{read_kind, write_kind, Entities, Entity, EntityId, EntityKind};
+use crate::replay::{invalid, read_varint, write_varint};
+use crate::state::TickState;
+
+/// Steps per pixel, 1/64 px is way below what's visible.
+pub const POS_SCALE: f32 = 64.0;
+/// Steps per pixel per tick.
+pub const VEL_SCALE: f32 = 256.0;
+/// Steps per pixel per tick per tick.
+pub const ACCEL_SCALE: f32 = 256.0;
+
+/// One entity's movement in fixed point.
+pub type QuantizedMovement = [i32; 6];
+
+/// A tick in fixed point, what actually gets sent.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct Quantized {
+    pub next_id: u32,
+    /// Sorted by id.
+    pub entities: Vec<(EntityId, EntityKind, QuantizedMovement)>,
+}
+
+impl Quantized {
+    fn get(&self, id: EntityId) -> Option<&QuantizedMovement> {
+        let i = self.entities.binary_search_by_key(&id, |(id, ..)| *id).ok()?;
+        Some(&self.entities[i].2)
+    }
+}
+
+fn quantize_vec2(value: Vec2, scale: f32) -> [i32; 2] {
+    // `as` saturates, so huge values can't wrap around
+    [(value.x * scale).round() as i32, (value.y * scale).round() as i32]
+}
+
+fn dequantize_vec2([x, y]: [i32; 2], scale: f32) -> Vec2 {
+    Vec2::new(x as f32 / scale, y as f32 / scale)
+}
+
+fn quantize_entity(entity: &Entity) -> QuantizedMovement {
+    let movement = &entity.movement;
+    let [pos_x, pos_y] = quantize_vec2(movement.pos, POS_SCALE);
+    let [vel_x, vel_y] = quantize_vec2(movement.vel, VEL_SCALE);
+    let [accel_x, accel_y] = quantize_vec2(movement.accel, ACCEL_SCALE);
+    [pos_x, pos_y, vel_x, vel_y, accel_x, accel_y]
+}
+
+fn dequantize_entity(kind: EntityKind, quantized: &QuantizedMovement) -> Entity {
+    let [pos_x, pos_y, vel_x, vel_y, accel_x, accel_y] = *quantized;
+    let mut entity = Entity { kind, ..Default::default() };
+    let movement = &mut entity.movement;
+    movement.pos = dequantize_vec2([pos_x, pos_y], POS_SCALE);
+    movement.vel = dequantize_vec2([vel_x, vel_y], VEL_SCALE);
+    movement.accel = dequantize_vec2([accel_x, accel_y], ACCEL_SCALE);
+    entity
+}
+
+pub fn quantize(state: &TickState) -> Quantized {
+    let entities = state
+        .entities
+        .iter()
+        .map(|(id, entity)| (id, entity.kind, quantize_entity(entity)))
+        .collect();
+    Quantized { next_id: state.entities.next_id().0, entities }
+}
+
+pub fn dequantize(quantized: &Quantized) -> TickState {
+    let mut entities = Entities::default();
+    for (id, kind, movement) in quantized.entities.iter() {
+        entities.insert(*id, dequantize_entity(*kind, movement));
+    }
+    entities.set_next_id(EntityId(quantized.next_id));
+    TickState { entities }
+}
+
+/// If both would be sent as the same thing.
+pub fn same_quantized(a: &TickState, b: &TickState) -> bool {
+    quantize(a) == quantize(b)
+}
+
+/// Write `state`, as a difference to `baseline` if given.
+pub fn write_state(
+    w: &mut impl Write, state: &TickState, baseline: Option<&TickState>,
+) -> io::Result<()> {
+    let quantized = quantize(state);
+    let base = baseline.map(quantize).unwrap_or_default();
+    write_varint(w, quantized.next_id)?;
+    let len = u32::try_from(quantized.entities.len()).map_err(|_| invalid("too many entities"))?;
+    write_varint(w, len)?;
+    for (id, kind, movement) in quantized.entities.iter() {
+        write_varint(w, id.0)?;
+        write_kind(w, *kind)?;
+        let base = base.get(*id).copied().unwrap_or_default();
+        for (value, base) in movement.iter().zip(base) {
+            write_varint(w, zigzag(value.wrapping_sub(base)))?;
+        }
+    }
+    Ok(())
+}
+
+/// Read a state written by [`write_state`] with the same `baseline`.
+pub fn read_state(r: &mut impl Read, baseline: Option<&TickState>) -> io::Result<TickState> {
+    let base = baseline.map(quantize).unwrap_or_default();
+    let next_id = read_varint(r)?;
+    let len = read_varint(r)?;
+    let mut quantized = Quantized { next_id, entities: Vec::new() };
+    let mut last_id = None;
+    for _ in 0..len {
+        let id = EntityId(read_varint(r)?);
+        if last_id.is_some_and(|last_id| id <= last_id) || id.0 >= next_id {
+            return Err(invalid("entity ids out of order"));
+        }
+        last_id = Some(id);
+        let kind = read_kind(r)?;
+        let mut movement = base.get(id).copied().unwrap_or_default();
+        for value in movement.iter_mut() {
+            *value = value.wrapping_add(unzigzag(read_varint(r)?));
+        }
+        quantized.entities.push((id, kind, movement));
+    }
+    match quantized.entities.first() {
+        Some((EntityId::PLAYER, EntityKind::Player, _)) => {}
+        Some((EntityId::PLAYER, ..)) => return Err(invalid("player isn't a player")),
+        _ => return Err(invalid("no player")),
+    }
+    Ok(dequantize(&quantized))
+}
+
+/// Small negative numbers to small positive numbers: 0, -1, 1, -2, 2 -> 0, 1, 2, 3, 4
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn unzigzag(value: u32) -> i32 {
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// xorshift64, no rand crate
+    struct Rng(u64);
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        fn f32(&mut self, range: f32) -> f32 {
+            (self.next() >> 40) as f32 / (1u64 << 24) as f32 * 2.0 * range - range
+        }
+        fn vec2(&mut self, range: f32) -> Vec2 {
+            Vec2::new(self.f32(range), self.f32(range))
+        }
+        fn state(&mut self) -> TickState {
+            let mut state = TickState::default();
+            state.player_mut().movement.pos = self.vec2(10_000.0);
+            state.player_mut().movement.vel = self.vec2(100.0);
+            state.player_mut().movement.accel = self.vec2(10.0);
+            let npc = Entity::new(EntityKind::Npc, self.vec2(10_000.0));
+            state.entities.spawn(npc);
+            state
+        }
+    }
+
+    fn round_trip(state: &TickState, baseline: Option<&TickState>) -> (TickState, usize) {
+        let mut bytes = Vec::new();
+        write_state(&mut bytes, state, baseline).unwrap();
+        let mut r = bytes.as_slice();
+        let decoded = read_state(&mut r, baseline).unwrap();
+        assert!(r.is_empty());
+        (decoded, bytes.len())
+    }
+
+    #[test]
+    fn varint() {
+        for value in [0, 1, -1, 63, -64, 64, i32::MAX, i32::MIN] {
+            assert_eq!(unzigzag(zigzag(value)), value);
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, zigzag(value)).unwrap();
+            assert_eq!(read_varint(&mut bytes.as_slice()).unwrap(), zigzag(value));
+        }
+        assert!(read_varint(&mut [0xff; 6].as_slice()).is_err());
+        assert!(read_varint(&mut [0x80].as_slice()).is_err());
+    }
+
+    #[test]
+    fn round_trip_random() {
+        let mut rng = Rng(0x1234_5678);
+        for _ in 0..10_000 {
+            let state = rng.state();
+            let baseline = rng.state();
+            // nudged a little, like the next tick
+            let mut next = state.clone();
+            next.player_mut().movement.pos += rng.vec2(20.0);
+            next.player_mut().movement.vel += rng.vec2(1.0);
+
+            for (state, baseline) in
+                [(&state, None), (&state, Some(&baseline)), (&next, Some(&state))]
+            {
+                let (decoded, _) = round_trip(state, baseline);
+                // only off by the quantization
+                for ((_, a), (_, b)) in state.entities.iter().zip(decoded.entities.iter()) {
+                    let (a, b) = (&a.movement, &b.movement);
+                    assert!((a.pos - b.pos).abs().max_element() <= 0.5 / POS_SCALE + 1e-3);
+                    assert!((a.vel - b.vel).abs().max_element() <= 0.5 / VEL_SCALE + 1e-5);
+                    assert!((a.accel - b.accel).abs().max_element() <= 0.5 / ACCEL_SCALE + 1e-6);
+                }
+                assert!(same_quantized(state, &decoded));
+                // decoding again changes nothing
+                assert_eq!(round_trip(&decoded, baseline).0, decoded);
+            }
+        }
+    }
+
+    #[test]
+    fn deltas_are_small() {
+        let mut rng = Rng(42);
+        let state = rng.state();
+        let (_, full) = round_trip(&state, None);
+        let (_, same) = round_trip(&state, Some(&state));
+        let mut next = state.clone();
+        next.player_mut().movement.pos += Vec2::new(0.5, -0.5);
+        let (_, delta) = round_trip(&next, Some(&state));
+        assert_eq!(same, 2 + 2 * 8);
+        assert!(delta < full, "{} {}", delta, full);
+
+        // despawned and newly spawned entities between baseline and state
+        next.entities.despawn(EntityId(1));
+        next.entities.spawn(Entity::new(EntityKind::Npc, Vec2::new(3.0, 4.0)));
+        let (decoded, _) = round_trip(&next, Some(&state));
+        assert!(same_quantized(&decoded, &next));
+        assert_eq!(decoded.entities.next_id(), EntityId(3));
+        assert!(!decoded.entities.contains(EntityId(1)));
+    }
+
+    #[test]
+    fn player_first() {
+        let mut bytes = Vec::new();
+        write_state(&mut bytes, &TickState::default(), None).unwrap();
+        // next id, entity count, then the player's id and kind
+        assert_eq!(bytes[..4], [1, 1, 0, 0]);
+        bytes[3] = 1;
+        let err = read_state(&mut bytes.as_slice(), None).unwrap_err();
+        assert_eq!(err.to_string(), "player isn't a player");
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt::Display;
+
+/// How bad the pretend network is, in one direction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConditionerSettings {
+    pub name: &'static str,
+    /// Delay every packet gets, in seconds.
+    pub latency_secs: f32,
+    /// Up to this much more random delay, in seconds.
+    pub jitter_secs: f32,
+    /// Chance to drop a packet, 0.0..=1.0.
+    pub loss: f32,
+    /// Chance to send a packet twice, the copy gets its own delay.
+    pub duplication: f32,
+    /// Chance a packet can arrive after packets sent later than it.
+    pub reordering: f32,
+}
+
+impl ConditionerSettings {
+    pub const OFF: Self = Self {
+        name: "off",
+        latency_secs: 0.0,
+        jitter_secs: 0.0,
+        loss: 0.0,
+        duplication: 0.0,
+        reordering: 0.0,
+    };
+    pub const LAN: Self = Self {
+        name: "lan",
+        latency_secs: 0.005,
+        jitter_secs: 0.002,
+        loss: 0.001,
+        duplication: 0.0,
+        reordering: 0.0,
+    };
+    /// ~300ms round trip
+    pub const NEW_YORK_PERTH: Self = Self {
+        name: "new york - perth",
+        latency_secs: 0.15,
+        jitter_secs: 0.02,
+        loss: 0.02,
+        duplication: 0.01,
+        reordering: 0.02,
+    };
+    /// ~500ms round trip
+    pub const PERTH_CHINA: Self = Self {
+        name: "perth - china",
+        latency_secs: 0.25,
+        jitter_secs: 0.05,
+        loss: 0.05,
+        duplication: 0.01,
+        reordering: 0.05,
+    };
+    /// Everything at once.
+    pub const AWFUL: Self = Self {
+        name: "awful",
+        latency_secs: 0.1,
+        jitter_secs: 0.1,
+        loss: 0.2,
+        duplication: 0.05,
+        reordering: 0.2,
+    };
+
+    pub const PRESETS: [Self; 5] =
+        [Self::OFF, Self::LAN, Self::NEW_YORK_PERTH, Self::PERTH_CHINA, Self::AWFUL];
+
+    /// The next preset after this one, for cycling through them.
+    pub fn next_preset(&self) -> Self {
+        let i = Self::PRESETS.iter().position(|preset| preset == self);
+        Self::PRESETS[i.map_or(0, |i| (i + 1) % Self::PRESETS.len())].clone()
+    }
+
+    pub fn is_off(&self) -> bool {
+        self.latency_secs == 0.0
+            && self.jitter_secs == 0.0
+            && self.loss == 0.0
+            && self.duplication == 0.0
+            && self.reordering == 0.0
+    }
+}
+
+impl Default for ConditionerSettings {
+    fn default() -> Self {
+        Self::OFF
+    }
+}
+
+impl Display for ConditionerSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}+{}ms, loss {}%, dup {}%, reorder {}%",
+            self.name,
+            self.latency_secs * 1000.0,
+            self.jitter_secs * 1000.0,
+            self.loss * 100.0,
+            self.duplication * 100.0,
+            self.reordering * 100.0
+        )
+    }
+}
+
+/// Pretend network in one direction, packets go in and come out later, or never.
+/// Time is passed in so it's deterministic, same seed same network.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conditioner<T> {
+    pub settings: ConditionerSettings,
+    /// Packets on their way and when they arrive, unsorted.
+    in_flight: Vec<(f64, T)>,
+    /// When the last packet that has to stay in order arrives.
+    last_arrival: f64,
+    rng: u64,
+    pub dropped: usize,
+    pub duplicated: usize,
+    pub reordered: usize,
+}
+
+impl<T: Clone> Conditioner<T> {
+    pub fn new(settings: ConditionerSettings, seed: u64) -> Self {
+        Self {
+            settings,
+            in_flight: Vec::new(),
+            last_arrival: 0.0,
+            // xorshift can't start at 0
+            rng: seed.max(1),
+            dropped: 0,
+            duplicated: 0,
+            reordered: 0,
+        }
+    }
+
+    /// xorshift64, good enough for rolling dice.
+    fn random(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn roll(&mut self, chance: f32) -> bool {
+        chance > 0.0 && self.random() < chance
+    }
+
+    /// Packets still on their way.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Send a packet at `time` seconds.
+    pub fn send(&mut self, packet: T, time: f64) {
+        if self.roll(self.settings.loss) {
+            self.dropped += 1;
+            return;
+        }
+        let copies = if self.roll(self.settings.duplication) {
+            self.duplicated += 1;
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let delay = self.settings.latency_secs + self.random() * self.settings.jitter_secs;
+            let mut arrival = time + delay as f64;
+            if self.roll(self.settings.reordering) {
+                // can overtake or be overtaken
+                self.reordered += 1;
+                arrival += (self.random() * self.settings.jitter_secs.max(0.05)) as f64;
+            } else {
+                // jitter alone doesn't reorder, like on a real connection
+                arrival = arrival.max(self.last_arrival);
+                self.last_arrival = arrival;
+            }
+            self.in_flight.push((arrival, packet.clone()));
+        }
+    }
+
+    /// Every packet that arrived by `time`, in arrival order.
+    pub fn receive(&mut self, time: f64) -> Vec<T> {
+        let mut arrived = Vec::new();
+        let mut i = 0;
+        while i < self.in_flight.len() {
+            if self.in_flight[i].0 <= time {
+                arrived.push(self.in_flight.swap_remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        arrived.sort_by(|a, b| a.0.total_cmp(&b.0));
+        arrived.into_iter().map(|(_, packet)| packet).collect()
+    }
+}
+
+impl<T: Clone> Default for Conditioner<T> {
+    fn default() -> Self {
+        Self::new(ConditionerSettings::OFF, 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn off_is_instant() {
+        let mut conditioner = Conditioner::default();
+        for i in 0..100 {
+            conditioner.send(i, i as f64);
+        }
+        assert_eq!(conditioner.receive(99.0), (0..100).collect::<Vec<_>>());
+
+        assert!(ConditionerSettings::OFF.is_off());
+        let reorder_only = ConditionerSettings { reordering: 0.1, ..ConditionerSettings::OFF };
+        assert!(!reorder_only.is_off());
+    }
+
+    #[test]
+    fn awful() {
+        let mut conditioner = Conditioner::new(ConditionerSettings::AWFUL, 1234);
+        let settings = conditioner.settings.clone();
+        for i in 0..1000 {
+            conditioner.send(i, i as f64 * 0.01);
+        }
+        assert!(conditioner.receive(settings.latency_secs as f64 - 0.001).is_empty());
+        let received = conditioner.receive(100.0);
+        assert_eq!(conditioner.in_flight(), 0);
+
+        let expected = 1000 - conditioner.dropped + conditioner.duplicated;
+        assert_eq!(received.len(), expected);
+        // about what the settings say
+        assert!((150..250).contains(&conditioner.dropped), "{}", conditioner.dropped);
+        assert!((20..80).contains(&conditioner.duplicated), "{}", conditioner.duplicated);
+        assert!(received.windows(2).any(|w| w[0] > w[1]));
+    }
+}
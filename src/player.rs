@@ -2,11 +2,10 @@ use std::fmt::Display;
 
 use macroquad::prelude::*;
 
-use crate::movement::Movement;
-use crate::{lerp_precise2, DBG_NOW, DBG_PREV};
+use crate::input::InputSample;
+use crate::lerp_precise2;
 
 // TODO: list benefits
-// TODO: mean, ignore zero
 /// How to average the player input.
 /// # Philosophy
 /// You want to collect player input as often as possible to reduce latency,
@@ -18,6 +17,9 @@ use crate::{lerp_precise2, DBG_NOW, DBG_PREV};
 /// # Notes
 /// `MeanIgnoreZero` and `MeanNormalized` reduces the need for null cancelling movement
 /// by about 50% or smth but still allows for null movement (is that ever even useful?)
+///
+/// Only `TimeWeightedMean` and `DecayingMean` look at how long each sample was held,
+/// the rest weight a 1 ms frame the same as a 30 ms hitch.
 #[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub enum AveragingStrategy {
     /// Worst latency, can miss inputs.
@@ -37,57 +39,103 @@ pub enum AveragingStrategy {
     MeanNormalized,
     // Mean lerped with MeanNormalized. 0 -> mean, 1.0 -> Normalized
     MeanNormalizedPercent(f32),
-    // hmm very interesting middle ground between Mean and MeanIgnoreZero/Normalized
-    // MeanIgnoreFirstXZeros(usize),
+    /// Mean, but the first X zero inputs are ignored.
+    /// hmm very interesting middle ground between Mean and MeanIgnoreZero/Normalized
+    MeanIgnoreFirstXZeros(usize),
+    /// Mean weighted by how long each input was held. Same result no matter the fps
+    /// or frame pacing.
+    TimeWeightedMean,
+    /// Time weighted mean, with older inputs counting less and less. The value is the
+    /// half-life in seconds, an input that many seconds older than the newest counts half.
+    DecayingMean(f32),
 }
 
 impl AveragingStrategy {
     // expect about 33 items to average for 1000hz and 10 tps
-    pub fn average(&self, dirs: &[Vec2]) -> Vec2 {
+    pub fn average(&self, samples: &[InputSample]) -> Vec2 {
         match *self {
-            AveragingStrategy::Oldest => Self::oldest(dirs),
-            AveragingStrategy::Newest => Self::newest(dirs),
-            AveragingStrategy::Mean => Self::mean(dirs),
-            AveragingStrategy::MeanIgnoreZero => Self::mean_ignore_zero(dirs),
-            AveragingStrategy::MeanNormalized => Self::mean_normalized(dirs),
+            AveragingStrategy::Oldest => Self::oldest(samples),
+            AveragingStrategy::Newest => Self::newest(samples),
+            AveragingStrategy::Mean => Self::mean(samples),
+            AveragingStrategy::MeanIgnoreZero => Self::mean_ignore_zero(samples),
+            AveragingStrategy::MeanNormalized => Self::mean_normalized(samples),
             AveragingStrategy::MeanNormalizedPercent(percent) => {
-                Self::mean_normalized_percent(dirs, percent)
+                Self::mean_normalized_percent(samples, percent)
             }
+            AveragingStrategy::MeanIgnoreFirstXZeros(x) => {
+                Self::mean_ignore_first_x_zeros(samples, x)
+            }
+            AveragingStrategy::TimeWeightedMean => Self::time_weighted_mean(samples),
+            AveragingStrategy::DecayingMean(half_life) => Self::decaying_mean(samples, half_life),
         }
     }
 
-    fn oldest(dirs: &[Vec2]) -> Vec2 {
-        dirs.first().copied().unwrap_or(Vec2::ZERO)
+    fn oldest(samples: &[InputSample]) -> Vec2 {
+        samples.first().map(|sample| sample.dir).unwrap_or(Vec2::ZERO)
     }
 
-    fn newest(dirs: &[Vec2]) -> Vec2 {
-        dirs.last().copied().unwrap_or(Vec2::ZERO)
+    fn newest(samples: &[InputSample]) -> Vec2 {
+        samples.last().map(|sample| sample.dir).unwrap_or(Vec2::ZERO)
     }
 
-    // FIXME: handle div zero
-    fn mean(dirs: &[Vec2]) -> Vec2 {
-        dirs.iter().sum::<Vec2>() / dirs.len().max(1) as f32
+    fn mean(samples: &[InputSample]) -> Vec2 {
+        samples.iter().map(|sample| &sample.dir).sum::<Vec2>() / samples.len().max(1) as f32
     }
 
     //
-    fn mean_ignore_zero(dirs: &[Vec2]) -> Vec2 {
-        let (sum, n) = dirs.iter().fold((Vec2::ZERO, 0), |(sum, n), &dir| {
-            let is_not_zero = (dir != Vec2::ZERO) as usize;
-            (sum + dir, n + is_not_zero)
+    fn mean_ignore_zero(samples: &[InputSample]) -> Vec2 {
+        let (sum, n) = samples.iter().fold((Vec2::ZERO, 0), |(sum, n), sample| {
+            let is_not_zero = (sample.dir != Vec2::ZERO) as usize;
+            (sum + sample.dir, n + is_not_zero)
         });
         sum / n.max(1) as f32
     }
 
-    fn mean_normalized(dirs: &[Vec2]) -> Vec2 {
-        Self::mean(dirs).normalize_or_zero()
+    fn mean_normalized(samples: &[InputSample]) -> Vec2 {
+        Self::mean(samples).normalize_or_zero()
     }
 
-    fn mean_normalized_percent(dirs: &[Vec2], percent: f32) -> Vec2 {
-        let mean = Self::mean(dirs);
+    fn mean_normalized_percent(samples: &[InputSample], percent: f32) -> Vec2 {
+        let mean = Self::mean(samples);
         let norm = mean.normalize_or_zero();
         // precise because require output to be 0..=1
         lerp_precise2(mean, norm, percent)
     }
+
+    fn mean_ignore_first_x_zeros(samples: &[InputSample], x: usize) -> Vec2 {
+        let zeros = samples.iter().filter(|sample| sample.dir == Vec2::ZERO).count();
+        let n = samples.len() - zeros.min(x);
+        // zeros dont add to the sum, only need to not count them
+        samples.iter().map(|sample| &sample.dir).sum::<Vec2>() / n.max(1) as f32
+    }
+
+    fn time_weighted_mean(samples: &[InputSample]) -> Vec2 {
+        Self::weighted_mean(samples, |sample| sample.duration)
+    }
+
+    fn decaying_mean(samples: &[InputSample], half_life: f32) -> Vec2 {
+        let Some(newest) = samples.last() else {
+            return Vec2::ZERO;
+        };
+        Self::weighted_mean(samples, |sample| {
+            let age = (newest.time - sample.time) as f32;
+            sample.duration * 0.5f32.powf(age / half_life)
+        })
+    }
+
+    /// Falls back to the plain mean if all the weights are zero,
+    /// like when the samples dont have durations.
+    fn weighted_mean(samples: &[InputSample], weight: impl Fn(&InputSample) -> f32) -> Vec2 {
+        let (sum, total_weight) = samples.iter().fold((Vec2::ZERO, 0.0), |(sum, total), sample| {
+            let weight = weight(sample);
+            (sum + sample.dir * weight, total + weight)
+        });
+        if total_weight.is_normal() {
+            sum / total_weight
+        } else {
+            Self::mean(samples)
+        }
+    }
 }
 
 impl Display for AveragingStrategy {
@@ -101,18 +149,24 @@ impl Display for AveragingStrategy {
             AveragingStrategy::MeanNormalizedPercent(percent) => {
                 write!(f, "Mean {}% normalized", percent)
             }
+            AveragingStrategy::MeanIgnoreFirstXZeros(x) => {
+                write!(f, "Mean ignore first {} zeros", x)
+            }
+            AveragingStrategy::TimeWeightedMean => write!(f, "TimeWeightedMean"),
+            AveragingStrategy::DecayingMean(half_life) => {
+                write!(f, "Decaying mean, {}s half-life", half_life)
+            }
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Default)]
-pub struct Player {
-    pub movement: Movement,
-}
+/// How players move. NPCs move the same way, see [`Entity`](crate::entity::Entity).
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Player;
 
 impl Player {
-    const PLAYER_SIZE: f32 = 10.0;
-    const PLAYER_COLOR: Color = BLACK;
+    /// Radius, also what hits are tested against.
+    pub const PLAYER_SIZE: f32 = 10.0;
     const MAX_SPEED: f32 = 15.0;
     const ACCEL: f32 = 2.5;
     const BASE_FRICTION: f32 = 0.5;
@@ -134,66 +188,6 @@ impl Player {
         Self::SCALING_FRICTION * (speed_factor)
     }
 
-    pub fn handle_movement(&mut self, wish_dir: Vec2, accel: f32) -> &mut Self {
-        // let dir = Self::average_input(desired_dir);
-        self.movement.accel = wish_dir * accel;
-        self
-    }
-
-    pub fn desired_dir() -> Vec2 {
-        let mut dir = Vec2::ZERO;
-        if is_key_down(KeyCode::W) || is_key_down(KeyCode::Up) {
-            dir.y += -1.0;
-        }
-        if is_key_down(KeyCode::S) || is_key_down(KeyCode::Down) {
-            dir.y += 1.0;
-        }
-        if is_key_down(KeyCode::A) || is_key_down(KeyCode::Left) {
-            dir.x += -1.0;
-        }
-        if is_key_down(KeyCode::D) || is_key_down(KeyCode::Right) {
-            dir.x += 1.0;
-        }
-        dir.normalize_or_zero()
-    }
-
-    pub fn draw(&self, prev: &Self, t: f32) -> &Self {
-        // let Vec2 { x, y } = self.movement.pos;
-        // let Vec2 { x, y } = lerp_fast2(prev.movement.pos, self.movement.pos, t);
-        // let Vec2 { x, y } = lerp_fast2(prev.movement.pos, self.movement.pos, t);
-        // let Vec2 {x,y} = prev.movement.interp0(&self.movement, t);
-        // let Vec2 {x,y} = prev.movement.interp1(&self.movement, t);
-        // let Vec2 {x,y} = prev.movement.interp2(&self.movement, t);
-        // let Vec2 { x, y } = prev.movement.interp3(&self.movement, t);
-        let Vec2 { x, y } = prev.movement.interp(&self.movement, t);
-        draw_circle(x, y, Self::PLAYER_SIZE, Self::PLAYER_COLOR);
-        self
-    }
-
-    pub fn draw_dbg(&self, prev: &Self, _t: f32) -> &Self {
-        // let Vec2 { x, y } = self.movement.pos;
-        // let Vec2 { x, y } = lerp_fast2(prev.movement.pos, self.movement.pos, t);
-        // let Vec2 { x, y } = lerp_fast2(prev.movement.pos, self.movement.pos, t);
-        let Vec2 { x, y } = prev.movement.interp0(&self.movement, 1.0);
-        // let Vec2 {x,y} = prev.movement.interp1(&self.movement, t);
-        // let Vec2 {x,y} = prev.movement.interp2(&self.movement, t);
-        // let Vec2 {x,y} = prev.movement.interp3(&self.movement, 1.0);
-        draw_circle(x, y, Self::PLAYER_SIZE, DBG_NOW);
-        self
-    }
-
-    pub fn draw_dbg_prev(&self, prev: &Self, _t: f32) -> &Self {
-        // let Vec2 { x, y } = self.movement.pos;
-        // let Vec2 { x, y } = lerp_fast2(prev.movement.pos, self.movement.pos, t);
-        // let Vec2 { x, y } = lerp_fast2(prev.movement.pos, self.movement.pos, t);
-        let Vec2 { x, y } = prev.movement.interp0(&self.movement, 0.0);
-        // let Vec2 {x,y} = prev.movement.interp1(&self.movement, t);
-        // let Vec2 {x,y} = prev.movement.interp2(&self.movement, t);
-        // let Vec2 {x,y} = prev.movement.interp3(&self.movement, 0.0);
-        draw_circle(x, y, Self::PLAYER_SIZE, DBG_PREV);
-        self
-    }
-
     // // mean. TODO: test bias latest input
     // // TODO: raw mean vs normalized vs mixed
     // // #[allow(clippy::let_and_return)]
@@ -209,3 +203,50 @@ impl Player {
     //     ret
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Same input held for the same time, but read at different frame rates.
+    fn held(dirs: &[(Vec2, f32)], fps: f32) -> Vec<InputSample> {
+        let mut samples = Vec::new();
+        let mut time = 0.0;
+        for &(dir, secs) in dirs {
+            for _ in 0..(secs * fps).round() as usize {
+                time += fps.recip() as f64;
+                samples.push(InputSample::with_duration(dir, time, fps.recip()));
+            }
+        }
+        samples
+    }
+
+    #[test]
+    fn time_weighted_ignores_frame_pacing() {
+        // 1 frame right at 30 fps (a hitch), then 1000 fps of nothing
+        let mut samples = held(&[(Vec2::X, 1.0 / 30.0)], 30.0);
+        samples.extend(held(&[(Vec2::ZERO, 1.0 / 30.0)], 1000.0));
+
+        let avg = AveragingStrategy::TimeWeightedMean.average(&samples);
+        assert!((avg.x - 0.5).abs() < 1e-2, "{}", avg);
+        assert!(AveragingStrategy::Mean.average(&samples).x < 0.1);
+    }
+
+    #[test]
+    fn decaying_prefers_newest() {
+        let samples = held(&[(Vec2::X, 0.05), (Vec2::Y, 0.05)], 144.0);
+        let avg = AveragingStrategy::DecayingMean(0.01).average(&samples);
+        assert!(avg.y > 0.9 && avg.x < 0.1, "{}", avg);
+        let avg = AveragingStrategy::TimeWeightedMean.average(&samples);
+        assert!((avg.x - avg.y).abs() < 1e-3, "{}", avg);
+    }
+
+    #[test]
+    fn ignore_first_x_zeros() {
+        let samples = held(&[(Vec2::ZERO, 0.02), (Vec2::X, 0.02)], 100.0);
+        let avg = AveragingStrategy::MeanIgnoreFirstXZeros(1).average(&samples);
+        assert_eq!(avg, Vec2::X * 2.0 / 3.0);
+        assert_eq!(AveragingStrategy::MeanIgnoreFirstXZeros(5).average(&samples), Vec2::X);
+        assert_eq!(AveragingStrategy::Mean.average(&[]), Vec2::ZERO);
+    }
+}
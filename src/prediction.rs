@@ -0,0 +1,205 @@
+use std::collections::VecDeque;
+
+use macroquad::math::Vec2;
+
+use crate::net::{codec, Snapshot};
+use crate::state::{Simulation, TickInput, TickNumber};
+
+/// Client side prediction. The client runs its own ticks right away instead of waiting
+/// for the server, and fixes them up when the server disagrees.
+///
+/// Every predicted tick's input is kept until the server confirms it. When a snapshot says
+/// the server saw it differently, that tick is overwritten and every tick after it is
+/// simulated again with the kept input. The jump is blended away over `blend_secs`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prediction {
+    pub enabled: bool,
+    /// About how long blending away a misprediction takes in seconds.
+    pub blend_secs: f32,
+    /// Input the server hasn't confirmed yet: (input sequence, tick number, input).
+    pending: VecDeque<(usize, TickNumber, TickInput)>,
+    /// Offset from the real position still being blended away.
+    pub correction: Vec2,
+    /// How far off the last misprediction was.
+    pub last_error: f32,
+    /// How many ticks were simulated again in total.
+    pub resimulated_ticks: usize,
+    pub mispredictions: usize,
+}
+
+impl Prediction {
+    const DEFAULT_BLEND_SECS: f32 = 0.1;
+
+    /// Input waiting to be confirmed.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Forget everything, like when (re)connecting.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+        self.correction = Vec2::ZERO;
+    }
+
+    /// Step one tick locally, `sequence` is what the tick's input was sent as.
+    pub fn predict(&mut self, sim: &mut Simulation, input: TickInput, sequence: usize) {
+        sim.tick_with(input);
+        self.pending.push_back((sequence, sim.game.tick_number, input));
+        // server is gone or way behind, no point keeping what's not in the buffer anymore
+        while self.pending.len() > sim.game.buffer().len() {
+            self.pending.pop_front();
+        }
+    }
+
+    /// Check a snapshot against what was predicted and simulate again from there if needed.
+    pub fn reconcile(&mut self, sim: &mut Simulation, snapshot: &Snapshot) {
+        // the server's spawn, npcs and walls are placed around it
+        sim.set_spawn(snapshot.spawn);
+        let Some(i) = self.pending.iter().position(|(seq, ..)| *seq == snapshot.last_input) else {
+            // already confirmed, or from before predicting
+            return;
+        };
+        let (_, tick_number, _) = self.pending[i];
+        self.pending.drain(..=i);
+
+        if snapshot.settings != sim.global.tick_settings {
+            sim.global.set_tick_settings(&mut sim.game, snapshot.settings.clone());
+        }
+        let before = sim.game.current_tick().player().movement.pos;
+        let Ok(tick) = sim.game.get_tick_mut(tick_number) else {
+            return; // too old
+        };
+        // snapshots are quantized, anything closer than that is a hit
+        if codec::same_quantized(tick, &snapshot.state) {
+            return;
+        }
+
+        let error = tick.player().movement.pos.distance(snapshot.state.player().movement.pos);
+        *tick = snapshot.state.clone();
+        for &(_, tick_number, input) in self.pending.iter() {
+            let Some(prev) = tick_number.checked_sub(1).and_then(|n| sim.game.get_tick(n).ok())
+            else {
+                continue;
+            };
+            let prev = prev.clone();
+            let Ok(tick) = sim.game.get_tick_mut(tick_number) else {
+                continue;
+            };
+            *tick = prev;
+            tick.step(&sim.global.tick_settings, &input, sim.spawn, &sim.world);
+            self.resimulated_ticks += 1;
+        }
+
+        self.correction += before - sim.game.current_tick().player().movement.pos;
+        self.last_error = error;
+        self.mispredictions += 1;
+    }
+
+    /// Blend the correction away, returns what to add to the drawn position.
+    pub fn update(&mut self, delta_time: f32) -> Vec2 {
+        self.correction *= (-delta_time / self.blend_secs).exp();
+        self.correction
+    }
+}
+
+impl Default for Prediction {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            blend_secs: Self::DEFAULT_BLEND_SECS,
+            pending: VecDeque::new(),
+            correction: Vec2::ZERO,
+            last_error: 0.0,
+            resimulated_ticks: 0,
+            mispredictions: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPAWN: Vec2 = Vec2::ZERO;
+
+    /// A snapshot of `server` saying it used input `sequence`.
+    fn snapshot(server: &Simulation, sequence: usize) -> Snapshot {
+        Snapshot::new(server, sequence)
+    }
+
+    #[test]
+    fn reconcile() {
+        let mut client = Simulation::new(30.0, SPAWN).unwrap();
+        let mut server = Simulation::new(30.0, SPAWN).unwrap();
+        let mut prediction = Prediction::default();
+        let right = TickInput { wish_dir: Vec2::X, reset: false, ..Default::default() };
+        let up = TickInput { wish_dir: -Vec2::Y, reset: false, ..Default::default() };
+
+        // server agrees, nothing to do
+        for sequence in 1..=5 {
+            prediction.predict(&mut client, right, sequence);
+            server.tick_with(right);
+        }
+        prediction.reconcile(&mut client, &snapshot(&server, 5));
+        assert_eq!(prediction.mispredictions, 0);
+        assert_eq!(prediction.pending(), 0);
+        assert_eq!(client.game.current_tick(), server.game.current_tick());
+
+        // server got up instead of right for input 6, then the same as the client
+        for sequence in 6..=10 {
+            prediction.predict(&mut client, right, sequence);
+        }
+        let predicted = client.game.current_tick().player().movement.pos;
+        server.tick_with(up);
+        prediction.reconcile(&mut client, &snapshot(&server, 6));
+        assert_eq!(prediction.mispredictions, 1);
+        assert_eq!(prediction.resimulated_ticks, 4);
+        assert_eq!(prediction.pending(), 4);
+        for _ in 7..=10 {
+            server.tick_with(right);
+        }
+        assert_eq!(client.game.current_tick(), server.game.current_tick());
+
+        // smoothed, not snapped
+        let corrected = client.game.current_tick().player().movement.pos;
+        let drawn = corrected + prediction.update(1.0 / 144.0);
+        assert!(drawn.distance(predicted) < 0.1 * corrected.distance(predicted));
+        for _ in 0..144 {
+            prediction.update(1.0 / 144.0);
+        }
+        assert!(prediction.correction.length() < 0.01);
+
+        // later snapshots agree again, old ones are ignored
+        prediction.reconcile(&mut client, &snapshot(&server, 10));
+        prediction.reconcile(&mut client, &Snapshot { last_input: 6, ..snapshot(&server, 6) });
+        assert_eq!(prediction.mispredictions, 1);
+    }
+
+    #[test]
+    fn server_spawn() {
+        use crate::entity::{Entity, EntityKind};
+
+        // different window size, npcs circle somewhere else
+        let mut client = Simulation::new(30.0, SPAWN).unwrap();
+        let mut server = Simulation::new(30.0, Vec2::new(400.0, 300.0)).unwrap();
+        for sim in [&mut client, &mut server] {
+            let npc = Entity::new(EntityKind::Npc, Vec2::new(450.0, 300.0));
+            sim.game.current_tick_mut().entities.spawn(npc);
+        }
+        let mut prediction = Prediction::default();
+        let input = TickInput::default();
+
+        prediction.predict(&mut client, input, 1);
+        server.tick_with(input);
+        prediction.reconcile(&mut client, &snapshot(&server, 1));
+        assert_eq!(client.spawn(), server.spawn());
+        // only the ticks before the first snapshot are wrong
+        for sequence in 2..=10 {
+            prediction.predict(&mut client, input, sequence);
+            server.tick_with(input);
+        }
+        prediction.reconcile(&mut client, &snapshot(&server, 10));
+        assert_eq!(prediction.mispredictions, 1);
+        assert_eq!(client.game.current_tick(), server.game.current_tick());
+    }
+}
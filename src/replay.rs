@@ -0,0 +1,377 @@
+//! Recording every tick's input and replaying it bit-for-bit.
+//!
+//! # File format
+//! Little endian, floats stored as raw bits so nothing gets rounded.
+//! ```text
+//! magic "ITRC", version u16
+//! start tick number u64, start tick
+//! start TickSettings, start spawn 2 x f32
+//! tick count u32, then per tick:
+//!     flags u8 (1 = reset, 2 = new TickSettings follow, 4 = new spawn follows,
+//!               8 = fire follows)
+//!     wish dir 2 x f32, [TickSettings], [spawn 2 x f32], [fire target 2 x f32, subtick u8]
+//! ```
+//! TickSettings are tps, tick len, buffer secs f32, buffer len u32, speed factor f32.
+//!
+//! The start tick is next entity id u32, entity count u32, then per entity
+//! id u32, kind (see `entity::write_kind`), movement (pos, vel, accel) 6 x f32.
+//! Version 1 only had the player's movement there, versions before 3 can't fire.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use macroquad::math::Vec2;
+
+use crate::entity::{read_kind, write_kind, Entities, Entity, EntityId, EntityKind};
+use crate::movement::Movement;
+use crate::state::{Fire, TickInput, TickNumber, TickSettings, TickState};
+
+const MAGIC: &[u8; 4] = b"ITRC";
+const VERSION: u16 = 3;
+
+const FLAG_RESET: u8 = 1;
+const FLAG_SETTINGS: u8 = 2;
+const FLAG_SPAWN: u8 = 4;
+const FLAG_FIRE: u8 = 8;
+
+/// Everything needed to step one tick again.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct TickRecord {
+    pub input: TickInput,
+    /// Settings in effect during the tick.
+    pub settings: TickSettings,
+    /// Where a reset would teleport to.
+    pub spawn: Vec2,
+}
+
+/// A recorded session. Starts from the tick when recording started.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Recording {
+    pub start_tick_number: TickNumber,
+    pub start_state: TickState,
+    pub start_settings: TickSettings,
+    pub start_spawn: Vec2,
+    pub ticks: Vec<TickRecord>,
+}
+
+impl Recording {
+    pub fn new(
+        start_tick_number: TickNumber, start_state: TickState, start_settings: TickSettings,
+        start_spawn: Vec2,
+    ) -> Self {
+        Self { start_tick_number, start_state, start_settings, start_spawn, ticks: Vec::new() }
+    }
+
+    pub fn push(&mut self, record: TickRecord) {
+        self.ticks.push(record);
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write(&mut w)?;
+        w.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn write(&self, w: &mut impl Write) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+
+        w.write_all(&(self.start_tick_number.0 as u64).to_le_bytes())?;
+        write_state(w, &self.start_state)?;
+        write_settings(w, &self.start_settings)?;
+        write_vec2(w, self.start_spawn)?;
+
+        let len = u32::try_from(self.ticks.len()).map_err(|_| invalid("too many ticks"))?;
+        w.write_all(&len.to_le_bytes())?;
+        let mut settings = &self.start_settings;
+        let mut spawn = self.start_spawn;
+        for tick in self.ticks.iter() {
+            let mut flags = 0;
+            if tick.input.reset {
+                flags |= FLAG_RESET;
+            }
+            if tick.settings != *settings {
+                flags |= FLAG_SETTINGS;
+            }
+            if tick.spawn != spawn {
+                flags |= FLAG_SPAWN;
+            }
+            if tick.input.fire.is_some() {
+                flags |= FLAG_FIRE;
+            }
+
+            w.write_all(&[flags])?;
+            write_vec2(w, tick.input.wish_dir)?;
+            if flags & FLAG_SETTINGS != 0 {
+                write_settings(w, &tick.settings)?;
+                settings = &tick.settings;
+            }
+            if flags & FLAG_SPAWN != 0 {
+                write_vec2(w, tick.spawn)?;
+                spawn = tick.spawn;
+            }
+            if let Some(fire) = &tick.input.fire {
+                write_fire(w, fire)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(r: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a recording"));
+        }
+        let version = u16::from_le_bytes(read_bytes(r)?);
+        if !(1..=VERSION).contains(&version) {
+            return Err(invalid("unsupported recording version"));
+        }
+
+        let start_tick_number = TickNumber(u64::from_le_bytes(read_bytes(r)?) as usize);
+        let start_state = if version == 1 {
+            let mut state = TickState::default();
+            state.player_mut().movement = read_movement(r)?;
+            state
+        } else {
+            read_state(r)?
+        };
+        let start_settings = read_settings(r)?;
+        let start_spawn = read_vec2(r)?;
+        let mut recording = Self::new(start_tick_number, start_state, start_settings, start_spawn);
+
+        let len = u32::from_le_bytes(read_bytes(r)?);
+        let mut settings = recording.start_settings.clone();
+        let mut spawn = recording.start_spawn;
+        for _ in 0..len {
+            let [flags] = read_bytes(r)?;
+            let wish_dir = read_vec2(r)?;
+            if flags & FLAG_SETTINGS != 0 {
+                settings = read_settings(r)?;
+            }
+            if flags & FLAG_SPAWN != 0 {
+                spawn = read_vec2(r)?;
+            }
+            let fire = if flags & FLAG_FIRE != 0 { Some(read_fire(r)?) } else { None };
+            let input = TickInput { wish_dir, reset: flags & FLAG_RESET != 0, fire };
+            recording.push(TickRecord { input, settings: settings.clone(), spawn });
+        }
+        Ok(recording)
+    }
+}
+
+/// Plays back a [`Recording`] one tick at a time.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct Playback {
+    pub recording: Recording,
+    /// Index of the next tick to play.
+    pub index: usize,
+}
+
+impl Playback {
+    pub fn new(recording: Recording) -> Self {
+        Self { recording, index: 0 }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.index >= self.recording.ticks.len()
+    }
+}
+
+impl Iterator for Playback {
+    type Item = TickRecord;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.recording.ticks.get(self.index)?.clone();
+        self.index += 1;
+        Some(record)
+    }
+}
+
+pub(crate) fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub(crate) fn read_bytes<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// 7 bits per byte, high bit set if more bytes follow.
+pub(crate) fn write_varint(w: &mut impl Write, mut value: u32) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return w.write_all(&[byte]);
+        }
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+
+pub(crate) fn read_varint(r: &mut impl Read) -> io::Result<u32> {
+    let mut value = 0u32;
+    for shift in (0..35).step_by(7) {
+        let [byte] = read_bytes(r)?;
+        value |= ((byte & 0x7f) as u32).checked_shl(shift).unwrap_or(0);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("varint too long"))
+}
+
+pub(crate) fn write_f32(w: &mut impl Write, value: f32) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+pub(crate) fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+    Ok(f32::from_le_bytes(read_bytes(r)?))
+}
+
+pub(crate) fn write_vec2(w: &mut impl Write, value: Vec2) -> io::Result<()> {
+    write_f32(w, value.x)?;
+    write_f32(w, value.y)
+}
+pub(crate) fn read_vec2(r: &mut impl Read) -> io::Result<Vec2> {
+    Ok(Vec2::new(read_f32(r)?, read_f32(r)?))
+}
+
+pub(crate) fn write_movement(w: &mut impl Write, movement: &Movement) -> io::Result<()> {
+    write_vec2(w, movement.pos)?;
+    write_vec2(w, movement.vel)?;
+    write_vec2(w, movement.accel)
+}
+pub(crate) fn read_movement(r: &mut impl Read) -> io::Result<Movement> {
+    Ok(Movement { pos: read_vec2(r)?, vel: read_vec2(r)?, accel: read_vec2(r)? })
+}
+
+fn write_state(w: &mut impl Write, state: &TickState) -> io::Result<()> {
+    let entities = &state.entities;
+    w.write_all(&entities.next_id().0.to_le_bytes())?;
+    let len = u32::try_from(entities.len()).map_err(|_| invalid("too many entities"))?;
+    w.write_all(&len.to_le_bytes())?;
+    for (id, entity) in entities.iter() {
+        w.write_all(&id.0.to_le_bytes())?;
+        write_kind(w, entity.kind)?;
+        write_movement(w, &entity.movement)?;
+    }
+    Ok(())
+}
+fn read_state(r: &mut impl Read) -> io::Result<TickState> {
+    let next_id = EntityId(u32::from_le_bytes(read_bytes(r)?));
+    let len = u32::from_le_bytes(read_bytes(r)?);
+    let mut entities = Entities::default();
+    let mut last_id = None;
+    for _ in 0..len {
+        let id = EntityId(u32::from_le_bytes(read_bytes(r)?));
+        if last_id.is_some_and(|last_id| id <= last_id) || id >= next_id {
+            return Err(invalid("entity ids out of order"));
+        }
+        last_id = Some(id);
+        let entity = Entity { kind: read_kind(r)?, movement: read_movement(r)? };
+        entities.insert(id, entity);
+    }
+    entities.set_next_id(next_id);
+    match entities.get(EntityId::PLAYER) {
+        Some(player) if player.kind == EntityKind::Player => {}
+        Some(_) => return Err(invalid("player isn't a player")),
+        None => return Err(invalid("no player")),
+    }
+    Ok(TickState { entities })
+}
+
+pub(crate) fn write_fire(w: &mut impl Write, fire: &Fire) -> io::Result<()> {
+    write_vec2(w, fire.target)?;
+    w.write_all(&[fire.subtick])
+}
+pub(crate) fn read_fire(r: &mut impl Read) -> io::Result<Fire> {
+    let target = read_vec2(r)?;
+    if !target.is_finite() {
+        return Err(invalid("bad fire target"));
+    }
+    let [subtick] = read_bytes(r)?;
+    Ok(Fire { target, subtick })
+}
+
+pub(crate) fn write_settings(w: &mut impl Write, settings: &TickSettings) -> io::Result<()> {
+    write_f32(w, settings.tps)?;
+    write_f32(w, settings.tick_len_secs)?;
+    write_f32(w, settings.buffer_secs)?;
+    let buffer_len = u32::try_from(settings.buffer_len).map_err(|_| invalid("buffer too long"))?;
+    w.write_all(&buffer_len.to_le_bytes())?;
+    write_f32(w, settings.speed_factor)
+}
+pub(crate) fn read_settings(r: &mut impl Read) -> io::Result<TickSettings> {
+    let settings = TickSettings {
+        tps: read_f32(r)?,
+        tick_len_secs: read_f32(r)?,
+        buffer_secs: read_f32(r)?,
+        buffer_len: u32::from_le_bytes(read_bytes(r)?) as usize,
+        speed_factor: read_f32(r)?,
+    };
+    if !settings.is_sane() {
+        return Err(invalid("insane tick settings"));
+    }
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_entity_ids() {
+        let mut state = TickState::new(Vec2::ZERO);
+        state.entities.spawn(Entity::new(EntityKind::Npc, Vec2::X));
+        let mut file = Vec::new();
+        write_state(&mut file, &state).unwrap();
+        assert_eq!(read_state(&mut file.as_slice()).unwrap(), state);
+
+        // id of the npc, after next id, entity count, the player and its kind and movement
+        let npc_id = 4 + 4 + 4 + 1 + 6 * 4;
+        for id in [0, 2, u32::MAX] {
+            file[npc_id..npc_id + 4].copy_from_slice(&id.to_le_bytes());
+            let err = read_state(&mut file.as_slice()).unwrap_err();
+            assert_eq!(err.to_string(), "entity ids out of order");
+        }
+
+        // id 0 has to be the player, or it'd get steered like one
+        let mut file = Vec::new();
+        write_state(&mut file, &state).unwrap();
+        let player_kind = 4 + 4 + 4;
+        for kind in [EntityKind::Npc, EntityKind::Projectile { ticks_left: 5, subtick: 0 }] {
+            let mut bad = file[..player_kind].to_vec();
+            write_kind(&mut bad, kind).unwrap();
+            bad.extend_from_slice(&file[player_kind + 1..]);
+            let err = read_state(&mut bad.as_slice()).unwrap_err();
+            assert_eq!(err.to_string(), "player isn't a player");
+        }
+    }
+
+    #[test]
+    fn version_1() {
+        let movement = Movement { pos: Vec2::new(1.0, 2.0), vel: Vec2::X, accel: Vec2::Y };
+        let settings = TickSettings::default();
+        let mut file = Vec::new();
+        file.extend_from_slice(MAGIC);
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&7u64.to_le_bytes());
+        write_movement(&mut file, &movement).unwrap();
+        write_settings(&mut file, &settings).unwrap();
+        write_vec2(&mut file, Vec2::new(400.0, 300.0)).unwrap();
+        file.extend_from_slice(&0u32.to_le_bytes());
+
+        // only the player back then
+        let recording = Recording::read(&mut file.as_slice()).unwrap();
+        assert_eq!(recording.start_tick_number, TickNumber(7));
+        assert_eq!(recording.start_state.entities.len(), 1);
+        assert_eq!(recording.start_state.player().movement, movement);
+        assert_eq!(recording.start_settings, settings);
+    }
+}
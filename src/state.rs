@@ -1,48 +1,102 @@
-#![allow(clippy::result_unit_err)]
-
 pub mod ring_buffer;
-
+pub mod simulation;
+pub mod tick_buffer;
+pub mod tick_number;
+pub mod update_thread;
+
+pub use simulation::Simulation;
+pub use tick_number::{TickError, TickNumber};
+
+use crate::entity::{Entities, Entity, EntityId, EntityKind};
+use crate::extrapolation::Extrapolator;
+use crate::input::InputSample;
+use crate::movement::{InterpolationStrategy, Movement};
 use crate::player::AveragingStrategy;
 use crate::player::Player;
 use crate::state::ring_buffer::RingBuffer;
-use crate::time::Timer;
+use crate::time::Accumulator;
 use crate::time::Timings;
+use crate::world::World;
 use macroquad::math::Vec2;
 use macroquad::window::screen_height;
 use macroquad::window::screen_width;
+use std::fmt::Display;
 
 /// State of the game and a buffer of previous ticks.
-#[derive(Clone, Debug, PartialEq, Default)]
+///
+/// Always has at least [`GameState::MIN_BUFFER_LEN`] ticks, so the current and previous
+/// tick are always there.
+#[derive(Clone, Debug, PartialEq)]
 pub struct GameState {
-    // /// Tick index, icreases every tick
-    pub tick_number: usize,
+    /// Increases every tick.
+    pub tick_number: TickNumber,
     /// older ticks in front, newer in back
-    pub buffer: RingBuffer<TickState>,
+    buffer: RingBuffer<TickState>,
     // NOTE: GlobalState isnt here because of (re)borrow issues
     // cant do self.prev_tick_mut() and &self.global_state
 }
 
 #[allow(dead_code)]
 impl GameState {
-    pub fn new(buffer_len: usize) -> Self {
-        Self { buffer: RingBuffer::with_capacity(buffer_len), ..Self::default() }
+    /// Enough for a current and previous tick to interpolate between.
+    pub const MIN_BUFFER_LEN: usize = 2;
+
+    /// Buffer of `buffer_len` ticks, all the first tick with the player at `spawn` and
+    /// NPCs around it. Doesn't need a window.
+    pub fn new(buffer_len: usize, spawn: Vec2) -> Self {
+        Self::with_first_tick(buffer_len, &Self::first_tick(spawn))
+    }
+
+    /// Buffer of `buffer_len` ticks, all `first_tick`.
+    pub fn with_first_tick(buffer_len: usize, first_tick: &TickState) -> Self {
+        let buffer_len = buffer_len.max(Self::MIN_BUFFER_LEN);
+        let mut buffer = RingBuffer::with_capacity(buffer_len);
+        buffer.extend(std::iter::repeat_n(first_tick, buffer_len).cloned());
+        Self { tick_number: TickNumber::default(), buffer }
+    }
+
+    fn first_tick(spawn: Vec2) -> TickState {
+        let mut first_tick = TickState::new(spawn);
+        for offset in [Vec2::new(150.0, 0.0), Vec2::new(-150.0, 0.0)] {
+            first_tick.entities.spawn(Entity::new(EntityKind::Npc, spawn + offset));
+        }
+        first_tick
     }
 
+    /// Fill the buffer with the first tick, player in the middle of the screen.
     pub fn init(&mut self) -> &mut Self {
-        let mut player = Player::default();
-        player.movement.pos.x = screen_width() / 2.0;
-        player.movement.pos.y = screen_height() / 2.0;
+        self.init_at(Vec2::new(screen_width() / 2.0, screen_height() / 2.0))
+    }
 
-        let first_tick = TickState { player };
-        self.buffer.fill_to_capacity(&first_tick);
+    /// Fill the buffer with the first tick, player at `spawn` and NPCs around it.
+    /// Doesn't need a window.
+    pub fn init_at(&mut self, spawn: Vec2) -> &mut Self {
+        self.init_with(&Self::first_tick(spawn))
+    }
 
+    /// Fill the buffer with `first_tick`, keeping its length.
+    pub fn init_with(&mut self, first_tick: &TickState) -> &mut Self {
+        for tick in self.buffer.iter_mut() {
+            tick.clone_from(first_tick);
+        }
         self
     }
 
+    /// Every buffered tick, oldest first.
+    pub fn buffer(&self) -> &RingBuffer<TickState> {
+        &self.buffer
+    }
+
+    /// Change how many ticks are kept, see [`RingBuffer::resize`].
+    /// Never goes below [`GameState::MIN_BUFFER_LEN`].
+    pub fn resize_buffer(&mut self, buffer_len: usize) {
+        self.buffer.resize(buffer_len.max(Self::MIN_BUFFER_LEN));
+    }
+
     /// Returns whole seconds and ticks remainder passed
     pub fn gametime_passed(&self, tps: f32) -> (usize, usize) {
         // premptive f64
-        let tick = self.tick_number;
+        let tick = self.tick_number.0;
         // TODO: this much prec not needed
         let (gametime_secs, gametime_ticks) = if tps.fract() == 0.0 {
             let tps = tps as usize;
@@ -58,6 +112,7 @@ impl GameState {
     }
 
     pub fn current_tick(&self) -> &TickState {
+        // NOTE:PANIC: never empty, every constructor fills it
         self.buffer.back().unwrap()
     }
     pub fn current_tick_mut(&mut self) -> &mut TickState {
@@ -65,110 +120,410 @@ impl GameState {
     }
 
     pub fn prev_tick(&self) -> &TickState {
-        self.get_prev_tick(1).unwrap()
-    }
-    pub fn prev_tick_mut(&mut self) -> &TickState {
-        self.get_prev_tick_mut(1).unwrap()
+        // NOTE:PANIC: never shorter than MIN_BUFFER_LEN
+        self.buffer.get_back(1).unwrap()
     }
 
     // TODO: decide on api, get tick n, or get tick that is n ticks in the past
 
-    /// Get the tick `tick` ticks in the past. 0 is current tick, 1 is previous tick.
-    pub(crate) fn get_prev_tick(&self, tick: usize) -> Option<&TickState> {
-        // VecDeque::back(): self.get(self.len.wrapping_sub(1))
-        self.buffer.get(self.buffer.len().wrapping_sub(1 + tick))
+    /// Get the tick `back` ticks in the past. 0 is current tick, 1 is previous tick.
+    pub fn get_prev_tick(&self, back: usize) -> Result<&TickState, TickError> {
+        let buffer_len = self.buffer.len();
+        let i = (back < buffer_len).then(|| buffer_len - 1 - back);
+        let i = i.ok_or(TickError::TooOld { back, buffer_len })?;
+        Ok(&self.buffer[i])
     }
-    /// Get the tick `tick` ticks in the past. 0 is current tick, 1 is previous tick.
-    pub(crate) fn get_prev_tick_mut(&mut self, tick: usize) -> Option<&mut TickState> {
-        // VecDeque::back_mut(): self.get_mut(self.len.wrapping_sub(1))
-        self.buffer.get_mut(self.buffer.len().wrapping_sub(1 + tick))
+    /// Get the tick `back` ticks in the past. 0 is current tick, 1 is previous tick.
+    pub(crate) fn get_prev_tick_mut(&mut self, back: usize) -> Result<&mut TickState, TickError> {
+        let buffer_len = self.buffer.len();
+        let i = (back < buffer_len).then(|| buffer_len - 1 - back);
+        let i = i.ok_or(TickError::TooOld { back, buffer_len })?;
+        Ok(&mut self.buffer[i])
     }
 
-    pub(crate) fn get_tick(&self, tick_number: usize) -> Option<&TickState> {
+    /// How many ticks before the current tick `tick` is.
+    fn ticks_back(&self, tick: TickNumber) -> Result<usize, TickError> {
+        // NOTE:CHEATS: a client can ask for any tick
         let current = self.tick_number;
-        // None if tried get future tick. NOTE:CHEATS: should never happen
-        let prev = current.checked_sub(tick_number)?;
-        self.get_prev_tick(prev) // None if tried to get too old
+        self.tick_number.since(tick).ok_or(TickError::Future { tick, current })
     }
-    pub(crate) fn get_tick_mut(&mut self, tick_number: usize) -> Option<&mut TickState> {
-        let current = self.tick_number;
-        // None if tried get future tick. NOTE:CHEATS: should never happen
-        let prev = current.checked_sub(tick_number)?;
-        self.get_prev_tick_mut(prev) // None if tried to get too old
+
+    pub fn get_tick(&self, tick: TickNumber) -> Result<&TickState, TickError> {
+        self.get_prev_tick(self.ticks_back(tick)?)
+    }
+    pub(crate) fn get_tick_mut(&mut self, tick: TickNumber) -> Result<&mut TickState, TickError> {
+        self.get_prev_tick_mut(self.ticks_back(tick)?)
     }
 
-    /// Remove oldest tick, copy latest tick to current. Returns the now current tick
+    /// Reuse the oldest tick as a copy of the latest tick. Returns the now current tick
     /// (unmodified from the now previous tick)
     pub fn advance_tick(&mut self) -> &mut TickState {
-        // NOTE:PANIC: only panics when not `init()`ed
-
         self.tick_number += 1;
-        let latest_tick = self.buffer.back_mut().unwrap().clone();
-        self.buffer.pop_front(); // remove oldest
-        self.buffer.push_back(latest_tick); // copy latest
-        self.buffer.back_mut().unwrap()
+        self.buffer.advance_in_place();
+        self.current_tick_mut()
+    }
+
+    /// Reallocate the tick buffer to fit exactly, giving back what shrinking left over.
+    pub fn compact(&mut self) {
+        self.buffer.compact();
+    }
+
+    /// Make `state` the current tick, `tick_number` has to be newer than the current tick.
+    /// Ticks skipped in between are filled with copies of the old current tick.
+    pub fn push_tick(&mut self, tick_number: TickNumber, state: TickState) -> &mut Self {
+        let skipped = tick_number.since(self.tick_number.next()).unwrap_or(0);
+        if skipped >= self.buffer.len() {
+            self.init_with(&state);
+        } else {
+            for _ in 0..skipped {
+                self.advance_tick();
+            }
+            *self.advance_tick() = state;
+        }
+        self.tick_number = tick_number;
+        self
+    }
+
+    /// Move everything in every buffered tick by `offset`.
+    pub fn translate(&mut self, offset: Vec2) -> &mut Self {
+        for tick in self.buffer.iter_mut() {
+            tick.translate(offset);
+        }
+        self
+    }
+
+    /// Throw away the newest `ticks` ticks so an older tick is the current tick again.
+    /// The oldest tick is repeated to keep the buffer length. Keeps at least 1 tick.
+    pub fn rewind(&mut self, ticks: usize) -> &mut Self {
+        let len = self.buffer.len();
+        let ticks = ticks.min(len.saturating_sub(1)).min(self.tick_number.0);
+        self.buffer.truncate(len - ticks);
+        self.buffer.resize(len);
+        self.tick_number = self.tick_number.saturating_sub(ticks);
+        self
+    }
+
+    /// Which ticks to draw when looking `ticks_back` ticks before the current tick.
+    /// Returns how many ticks back the newer tick is and how far to interpolate towards it
+    /// from the tick before it. Clamped to what's in the buffer.
+    pub fn view_back(&self, ticks_back: f32) -> (usize, f32) {
+        let max = self.buffer.len().saturating_sub(2) as f32;
+        let ticks_back = ticks_back.clamp(0.0, max);
+        let back = ticks_back.floor();
+        (back as usize, 1.0 - (ticks_back - back))
+    }
+
+    /// The ticks to draw when looking `ticks_back` ticks before the current tick, see
+    /// [`GameState::view_back`].
+    pub fn view(&self, ticks_back: f32) -> View<'_> {
+        let (back, t) = self.view_back(ticks_back);
+        // view_back keeps both inside the buffer, these never fall back
+        let next = self.get_prev_tick(back).unwrap_or(self.current_tick());
+        let prev = self.get_prev_tick(back + 1).unwrap_or(next);
+        let before = self.get_prev_tick(back + 2).ok();
+        View { back, t, before, prev, next }
+    }
+
+    /// Advance to the next tick and move the player according to `input`.
+    /// `spawn` is where the player gets teleported to on reset.
+    pub fn update(
+        &mut self, tick_settings: &TickSettings, input: &TickInput, spawn: Vec2, world: &World,
+    ) {
+        self.advance_tick().step(tick_settings, input, spawn, world);
+    }
+}
+
+/// Ticks to interpolate between, `t` of the way from `prev` to `next`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct View<'a> {
+    /// How many ticks before the current tick `next` is.
+    pub back: usize,
+    pub t: f32,
+    /// The tick before `prev`, if it's still buffered.
+    pub before: Option<&'a TickState>,
+    pub prev: &'a TickState,
+    pub next: &'a TickState,
+}
+
+/// Everything the player did during one tick, already averaged.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct TickInput {
+    pub wish_dir: Vec2,
+    /// Teleport back to spawn and stop.
+    pub reset: bool,
+    pub fire: Option<Fire>,
+}
+
+/// A shot fired sometime during a tick.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Fire {
+    /// Where to shoot at.
+    pub target: Vec2,
+    /// How far into the tick it was fired, in 256ths. 0 is right after the previous tick.
+    pub subtick: u8,
+}
+
+impl Fire {
+    /// `fraction` of the way into the tick, 0.0..1.0.
+    pub fn new(target: Vec2, fraction: f32) -> Self {
+        Self { target, subtick: (fraction * 256.0).clamp(0.0, 255.0) as u8 }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        self.subtick as f32 / 256.0
     }
 }
 
 /// State of one tick
-#[derive(Clone, Debug, PartialEq, Default)]
+#[derive(Debug, PartialEq)]
 pub struct TickState {
-    pub player: Player,
+    /// Always has the player as [`EntityId::PLAYER`].
+    pub entities: Entities,
+}
+
+// by hand so clone_from reuses allocations, see RingBuffer::advance_in_place
+impl Clone for TickState {
+    fn clone(&self) -> Self {
+        Self { entities: self.entities.clone() }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.entities.clone_from(&source.entities);
+    }
+}
+
+impl TickState {
+    /// Only the player, at `spawn`.
+    pub fn new(spawn: Vec2) -> Self {
+        let mut entities = Entities::default();
+        entities.spawn(Entity::new(EntityKind::Player, spawn));
+        Self { entities }
+    }
+
+    pub fn player(&self) -> &Entity {
+        // NOTE:PANIC: the player is spawned first and never despawned
+        self.entities.get(EntityId::PLAYER).expect("player despawned")
+    }
+
+    pub fn player_mut(&mut self) -> &mut Entity {
+        self.entities.get_mut(EntityId::PLAYER).expect("player despawned")
+    }
+
+    /// Move every entity by `offset`, leaving how they move alone.
+    pub fn translate(&mut self, offset: Vec2) {
+        for (_, entity) in self.entities.iter_mut() {
+            entity.movement.pos += offset;
+        }
+    }
+
+    /// Move the player according to `input` and everything else on its own, turning a copy
+    /// of the previous tick into the next tick. `spawn` is where the player gets teleported
+    /// to on reset. Nothing goes through `world`.
+    pub fn step(
+        &mut self, tick_settings: &TickSettings, input: &TickInput, spawn: Vec2, world: &World,
+    ) {
+        let speed_factor = tick_settings.speed_factor;
+        let player_before = self.player().movement.pos;
+        for (_, entity) in self.entities.iter_mut() {
+            let wish_dir = match &mut entity.kind {
+                EntityKind::Player => input.wish_dir,
+                EntityKind::Npc => entity.npc_wish_dir(spawn),
+                EntityKind::Projectile { ticks_left, .. } => {
+                    *ticks_left = ticks_left.saturating_sub(1);
+                    let vel = entity.movement.vel;
+                    entity.fly(world, vel);
+                    continue;
+                }
+            };
+            entity.handle_movement(wish_dir, Player::accel(speed_factor));
+
+            if input.reset && entity.kind == EntityKind::Player {
+                entity.movement = Movement::default();
+                entity.movement.pos = spawn;
+            }
+
+            let radius = entity.radius();
+            entity.movement.step_in(
+                world,
+                radius,
+                Player::max_speed(speed_factor),
+                Player::base_friction(speed_factor),
+                Player::scaling_friction(speed_factor),
+            );
+        }
+        self.entities.retain(|_, entity| {
+            !matches!(entity.kind, EntityKind::Projectile { ticks_left: 0, .. })
+        });
+
+        if let Some(fire) = &input.fire {
+            let from = if input.reset { spawn } else { player_before };
+            let to = self.player().movement.pos;
+            let mut projectile = Entity::projectile(from, to, fire, tick_settings);
+            // fly the rest of the tick again, this time checking for walls
+            let movement = &mut projectile.movement;
+            let rest = movement.vel * (1.0 - fire.fraction());
+            movement.pos -= rest;
+            projectile.fly(world, rest);
+            self.entities.spawn(projectile);
+        }
+    }
+}
+
+impl Default for TickState {
+    fn default() -> Self {
+        Self::new(Vec2::ZERO)
+    }
+}
+
+/// Pausing, single stepping and scrubbing back through the tick buffer.
+/// For looking at what happened, see [`GlobalState::pause_updates`] for pretending ticks
+/// stopped arriving.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct TickControl {
+    /// Clock and ticks are frozen.
+    pub paused: bool,
+    /// How many ticks before the current tick are being looked at while paused.
+    /// Fractions are interpolated.
+    pub view_back: f32,
 }
 
 /// Player input, interp settings, dbg info settings.
 #[derive(Clone, Debug, PartialEq, Default)]
 pub struct GlobalState {
     /// Store input as fast as possible here until `update()`
-    pub input_buffer: Vec<Vec2>,
+    pub input_buffer: Vec<InputSample>,
     pub tick_settings: TickSettings,
     pub is_fullscreen: bool,
-    pub update_timer: Timer,
+    pub update_accumulator: Accumulator,
     pub avg_strategy: AveragingStrategy,
+    pub interp_strategy: InterpolationStrategy,
 
     pub timings: Timings,
+    /// Pausing to look through the tick buffer, freezes the clock too.
+    pub tick_control: TickControl,
+    /// Stop ticking, but keep the clock running. Pretends ticks stopped arriving, like a
+    /// stalled server, to see what extrapolation does. Unlike [`TickControl::paused`]
+    /// the missed ticks keep counting and drawing keeps going past the current tick.
+    pub pause_updates: bool,
+    /// Ticks that were due but didn't happen since the last tick.
+    pub missed_ticks: usize,
+    pub extrapolator: Extrapolator,
+    /// Extra ticks to draw behind the newest tick, on top of the 1 tick interpolating
+    /// always needs. Late ticks can then arrive before they're drawn.
+    pub interp_delay: f32,
     pub dont_interpolate: bool,
     pub dbg_buffer: bool,
     pub dbg_hide_interp_info: bool,
+    /// Why the last settings change or key press was refused and when, by macroquad's
+    /// `get_time()`.
+    pub dbg_refused: Option<(String, f64)>,
 }
 
 impl GlobalState {
-    pub fn new(tps: f32) -> Result<Self, ()> {
+    pub fn new(tps: f32) -> Result<Self, TickSettingsError> {
         let tick_settings = TickSettings::new(tps)?;
         Ok(Self {
-            update_timer: Timer::new(tick_settings.tick_len_secs),
+            update_accumulator: Accumulator::new(tick_settings.tick_len_secs),
             tick_settings,
             ..Default::default()
         })
     }
 
     pub fn tick_progress(&self) -> f32 {
-        1.0 - self.update_timer.time / self.tick_settings.tick_len_secs
+        self.update_accumulator.alpha()
+    }
+
+    /// Ticks passed since the current tick. Over 1.0 when ticks were missed.
+    pub fn render_progress(&self) -> f32 {
+        self.missed_ticks as f32 + self.tick_progress()
+    }
+
+    /// Pretend the clock advanced `secs` since the current tick, for when ticks are run
+    /// somewhere else and only arrive here, like on another thread.
+    pub fn set_time_since_tick(&mut self, secs: f32) {
+        let tick_len = self.tick_settings.tick_len_secs;
+        let ticks = (secs.max(0.0) / tick_len) as usize;
+        self.missed_ticks = ticks;
+        self.update_accumulator.time = (secs - ticks as f32 * tick_len).clamp(0.0, tick_len);
+    }
+
+    /// Where to draw, in ticks passed since the previous tick. Same as `render_progress()`
+    /// without an `interp_delay`, negative when drawing further back than the previous tick.
+    pub fn cursor_progress(&self) -> f32 {
+        self.render_progress() - self.interp_delay
+    }
+
+    /// Most `interp_delay` that fits in the tick buffer.
+    pub fn max_interp_delay(&self) -> f32 {
+        self.tick_settings.buffer_len.saturating_sub(2) as f32
+    }
+
+    /// Set how many extra ticks to draw behind the newest tick.
+    pub fn set_interp_delay(&mut self, ticks: f32) -> Result<(), InterpDelayError> {
+        let max = self.max_interp_delay();
+        if !(0.0..=max).contains(&ticks) {
+            return Err(InterpDelayError { ticks, max });
+        }
+        self.interp_delay = ticks;
+        Ok(())
+    }
+
+    /// Set the extra delay in seconds instead of ticks.
+    pub fn set_interp_delay_secs(&mut self, secs: f32) -> Result<(), InterpDelayError> {
+        self.set_interp_delay(secs * self.tick_settings.tps)
+    }
+
+    /// Extra delay in seconds.
+    pub fn interp_delay_secs(&self) -> f32 {
+        self.interp_delay / self.tick_settings.tps
+    }
+
+    /// Keep `interp_delay` inside the buffer after it got shorter.
+    fn clamp_interp_delay(&mut self) {
+        self.interp_delay = self.interp_delay.min(self.max_interp_delay());
     }
 
     pub fn set_timescale(&mut self, timescale: f32) -> &mut Self {
         self.tick_settings.set_timescale(timescale);
-        self.update_timer.update_from_tick_settings(&self.tick_settings);
+        self.update_accumulator.update_from_tick_settings(&self.tick_settings);
         self
     }
 
-    pub fn set_tps(&mut self, game_state: &mut GameState, tps: f32) -> Result<(), ()> {
+    pub fn set_tps(
+        &mut self, game_state: &mut GameState, tps: f32,
+    ) -> Result<(), TickSettingsError> {
         self.tick_settings = self.tick_settings.set_tps(tps)?;
-        game_state.buffer.resize(self.tick_settings.buffer_len);
-        self.update_timer.update_from_tick_settings(&self.tick_settings);
+        game_state.resize_buffer(self.tick_settings.buffer_len);
+        self.update_accumulator.update_from_tick_settings(&self.tick_settings);
+        self.clamp_interp_delay();
         Ok(())
     }
 
-    pub fn set_buffer_secs(&mut self, game_state: &mut GameState, secs: f32) -> Result<(), ()> {
+    pub fn set_buffer_secs(
+        &mut self, game_state: &mut GameState, secs: f32,
+    ) -> Result<(), TickSettingsError> {
         self.tick_settings.set_buffer_secs(secs)?;
-        game_state.buffer.resize(self.tick_settings.buffer_len);
-        self.update_timer.update_from_tick_settings(&self.tick_settings);
+        game_state.resize_buffer(self.tick_settings.buffer_len);
+        self.update_accumulator.update_from_tick_settings(&self.tick_settings);
+        self.clamp_interp_delay();
         Ok(())
     }
 
+    /// Replace all the tick settings at once, like when playing back a recording.
+    pub fn set_tick_settings(&mut self, game_state: &mut GameState, tick_settings: TickSettings) {
+        self.tick_settings = tick_settings;
+        game_state.resize_buffer(self.tick_settings.buffer_len);
+        self.update_accumulator.update_from_tick_settings(&self.tick_settings);
+        self.clamp_interp_delay();
+    }
+
     pub fn set_avg_strategy(&mut self, avg_strat: AveragingStrategy) {
         self.avg_strategy = avg_strat;
     }
+
+    pub fn set_interp_strategy(&mut self, interp_strat: InterpolationStrategy) {
+        self.interp_strategy = interp_strat;
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -191,9 +546,11 @@ impl TickSettings {
     /// At 30 tps, `speed_factor` will be 2.0. At 120 tps, `speed_factor` will be 0.5
     pub const REFERENCE_TPS: f32 = 60.0; // TODO: replace with "1 tps"
     const DEFAULT_BUFFER: f32 = 0.25;
+    /// Fewer ticks than this and the game gets too choppy to tell anything apart.
+    pub const MIN_TPS: f32 = 10.0;
 
     /// Create and initialize
-    pub fn new(tps: f32) -> Result<Self, ()> {
+    pub fn new(tps: f32) -> Result<Self, TickSettingsError> {
         Self {
             tps,
             buffer_secs: Self::DEFAULT_BUFFER,
@@ -205,28 +562,36 @@ impl TickSettings {
     }
 
     pub fn is_sane(&self) -> bool {
-        self.tps >= 10.0
-            && self.buffer_secs > 0.0
-            && self.buffer_len > 1
-            && self.speed_factor.is_normal()
+        self.check().is_ok()
+    }
+
+    /// Like [`TickSettings::is_sane`], but says what's wrong.
+    pub fn check(&self) -> Result<(), TickSettingsError> {
+        if self.tps.is_nan() || self.tps < Self::MIN_TPS {
+            return Err(TickSettingsError::TpsTooLow(self.tps));
+        }
+        if self.buffer_secs.is_nan() || self.buffer_secs <= 0.0 || self.buffer_len < 2 {
+            let (secs, len) = (self.buffer_secs, self.buffer_len);
+            return Err(TickSettingsError::BufferTooShort { secs, len });
+        }
+        if !self.speed_factor.is_normal() {
+            return Err(TickSettingsError::BadSpeedFactor(self.speed_factor));
+        }
+        Ok(())
     }
 
     /// Calculate rest of values from `tps` and `buffer_secs` and `REFERENCE_TPS`
-    pub fn calculate(&self) -> Result<Self, ()> {
+    pub fn calculate(&self) -> Result<Self, TickSettingsError> {
         let tick_len_secs = self.tps.recip();
         let buffer_len = (self.buffer_secs * self.tps).ceil() as usize;
         let speed_factor = Self::REFERENCE_TPS / self.tps;
 
         let new = Self { tick_len_secs, buffer_len, speed_factor, ..*self };
-        if new.is_sane() {
-            Ok(new)
-        } else {
-            Err(())
-        }
+        new.check().map(|()| new)
     }
 
     /// Sets the ticks per seconds. Recalculates, preserves timescale.
-    pub fn set_tps(&self, tps: f32) -> Result<Self, ()> {
+    pub fn set_tps(&self, tps: f32) -> Result<Self, TickSettingsError> {
         let mut new = self.clone();
         let timescale = self.timescale();
 
@@ -251,7 +616,7 @@ impl TickSettings {
         self
     }
 
-    pub fn set_buffer_secs(&mut self, secs: f32) -> Result<(), ()> {
+    pub fn set_buffer_secs(&mut self, secs: f32) -> Result<(), TickSettingsError> {
         let mut new = self.clone();
         let timescale = self.timescale();
 
@@ -268,4 +633,48 @@ impl Default for TickSettings {
     fn default() -> Self {
         Self::new(60.0).unwrap()
     }
-}
\ No newline at end of file
+}
+
+/// Which part of [`TickSettings::is_sane`] failed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TickSettingsError {
+    /// Below [`TickSettings::MIN_TPS`], or not a number.
+    TpsTooLow(f32),
+    /// Buffer doesn't fit a previous tick to interpolate from.
+    BufferTooShort { secs: f32, len: usize },
+    /// Zero, infinite or not a number, from a tps that's way too high.
+    BadSpeedFactor(f32),
+}
+
+impl Display for TickSettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TickSettingsError::TpsTooLow(tps) => {
+                write!(f, "tps {} is below the minimum of {}", tps, TickSettings::MIN_TPS)
+            }
+            TickSettingsError::BufferTooShort { secs, len } => {
+                write!(f, "buffer of {}s only holds {} ticks, needs at least 2", secs, len)
+            }
+            TickSettingsError::BadSpeedFactor(speed_factor) => {
+                write!(f, "speed factor {} isn't a normal number", speed_factor)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TickSettingsError {}
+
+/// Tried to set an `interp_delay` that doesn't fit in the tick buffer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InterpDelayError {
+    pub ticks: f32,
+    pub max: f32,
+}
+
+impl Display for InterpDelayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "interp delay of {} ticks is outside 0 to {}", self.ticks, self.max)
+    }
+}
+
+impl std::error::Error for InterpDelayError {}
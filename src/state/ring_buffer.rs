@@ -10,11 +10,15 @@ use std::ops::{Deref, DerefMut};
 // I think there needs to be skipping, in case ur internet poops out for like 4 secs
 // and you need like 240 ticks sent -> bad
 // how to handle skipping?
-// prob simple slice search, [(time, tick)], accounting for missing ticks
+// TickBuffer keeps what actually arrived, the gaps get guessed in here
 
 // TODO: is that even nessessary?
 // front = oldest, back = newest, think of it like normal Vec::push() order
-/// Thin wrapper around a [`VecDeque`] that will try to never have any excess capacity.
+/// Thin wrapper around a [`VecDeque`], used at a fixed length.
+///
+/// [`RingBuffer::advance_in_place`] reuses the oldest slot, so once it's full it never
+/// allocates again until [`RingBuffer::resize`]d bigger. Shrinking keeps the capacity,
+/// [`RingBuffer::compact`] gives it back.
 #[derive(Clone, PartialEq, Default)]
 pub struct RingBuffer<T>(pub VecDeque<T>);
 
@@ -26,15 +30,8 @@ impl<T: Debug> Debug for RingBuffer<T> {
 
 // previous n
 // current
-// "next" // just split off "next" into impl detail with threading
-// update thread owns "next" and queues into buffer on draw thread?
-
-// need someway to have update thread and draw thread go at once
-// (update reads and rights to 1, gets update from server too)
-// draw only reads (always "current" tick, never "next" tick)
-// -> they never overlap but need to sync sending "next" tick to draw
-// also also handle resizing
-// TODO: hmmmm use: update generates time, draw consumes time?
+// "next" is owned by the update thread when threaded, finished ticks get queued into the
+// draw thread's buffer, see update_thread.rs
 
 impl<T> RingBuffer<T> {
     pub fn new() -> RingBuffer<T> {
@@ -58,9 +55,34 @@ impl<T> RingBuffer<T> {
     pub fn get_back(&self, index: usize) -> Option<&T> {
         self.0.get(self.0.len().wrapping_sub(1 + index))
     }
+
+    /// Move everything into a new allocation that fits exactly. Unlike
+    /// [`VecDeque::shrink_to_fit`] this always reallocates, so it doesn't stay stuck in
+    /// a fragmented spot.
+    pub fn compact(&mut self) {
+        compact(&mut self.0);
+    }
+}
+
+/// [`RingBuffer::compact`] for any [`VecDeque`].
+pub(crate) fn compact<T>(deque: &mut VecDeque<T>) {
+    let mut compacted = VecDeque::with_capacity(deque.len());
+    compacted.extend(deque.drain(..));
+    *deque = compacted;
 }
 
 impl<T: Clone> RingBuffer<T> {
+    /// Reuse the oldest slot as a copy of the newest, with [`Clone::clone_from`] so its
+    /// allocations are reused too. Keeps the length. Returns the now newest element.
+    pub fn advance_in_place(&mut self) -> Option<&mut T> {
+        let mut slot = self.pop_front()?;
+        if let Some(newest) = self.back() {
+            slot.clone_from(newest);
+        }
+        self.push_back(slot);
+        self.back_mut()
+    }
+
     pub fn fill_to_capacity(&mut self, value: &T) {
         // let free_space = self.capacity() - self.len()
         for _ in 0..self.capacity() {
@@ -68,11 +90,11 @@ impl<T: Clone> RingBuffer<T> {
         }
     }
 
-    /// Resize to new len. If `new_len` is larger, fill new space with the last element.
-    /// If `new_len` is smaller, drop elements from the back.
+    /// Resize to new len. If `new_len` is larger, fill new space in the front with the oldest
+    /// element. If `new_len` is smaller, drop the oldest elements from the front.
     /// # Notes
     /// - Grows with [`VecDeque::reserve_exact()`], **O**(n) if repeatedly called
-    /// - Allocates complety new [`VecDeque`] when shrinking.
+    /// - Shrinks in place, the capacity stays until [`RingBuffer::compact()`].
     pub fn resize(&mut self, new_len: usize) {
         // if new_len is smaller, no-op
         let additional = new_len as isize - self.len() as isize;
@@ -88,9 +110,8 @@ impl<T: Clone> RingBuffer<T> {
             }
         } else {
             // Shrinking
-            // TODO: try std::mem::take to avoid cloning (is it optimized out?)
-            let new_deque = self.iter().rev().take(new_len).rev().cloned().collect::<VecDeque<_>>();
-            *self = Self(new_deque);
+            let excess = self.len() - new_len;
+            self.drain(..excess);
         };
     }
 }
@@ -142,8 +163,32 @@ mod tests {
         }
         ring.resize(16);
 
-        let mut truth = (0..8).collect::<Vec<_>>();
-        truth.resize(16, *truth.last().unwrap());
-        assert_eq!(truth, ring.make_contiguous())
+        // newest stay at the back, oldest gets repeated in the front
+        let mut truth = vec![0; 8];
+        truth.extend(0..8);
+        assert_eq!(truth, ring.make_contiguous());
+
+        ring.resize(4);
+        assert_eq!([4, 5, 6, 7], ring.make_contiguous());
+        assert!(ring.capacity() >= 16);
+        ring.compact();
+        assert_eq!(ring.capacity(), 4);
+        assert_eq!([4, 5, 6, 7], ring.make_contiguous());
+    }
+
+    #[test]
+    fn in_place() {
+        let mut ring = RingBuffer::with_capacity(3);
+        ring.extend([Vec::with_capacity(4), vec![2], vec![3, 3]]);
+        let capacity = ring.capacity();
+        let oldest = ring.front().unwrap().as_ptr();
+
+        // oldest slot gets reused, allocation and all
+        let newest = ring.advance_in_place().unwrap();
+        assert_eq!(*newest, [3, 3]);
+        newest.push(4);
+        assert_eq!(ring.back().unwrap().as_ptr(), oldest);
+        assert_eq!(ring.make_contiguous(), [vec![2], vec![3, 3], vec![3, 3, 4]]);
+        assert_eq!(ring.capacity(), capacity);
     }
 }
@@ -0,0 +1,658 @@
+use macroquad::math::Vec2;
+
+use crate::input::InputSource;
+use crate::net::Snapshot;
+use crate::replay::{Playback, Recording, TickRecord};
+use crate::state::tick_buffer::TickBuffer;
+use crate::state::{
+    Fire, GameState, GlobalState, TickControl, TickInput, TickNumber, TickSettingsError, TickState,
+};
+use crate::world::World;
+use crate::{hermite2, lerp_precise2};
+
+/// Fixed tick simulation that doesn't touch the window, keyboard or clock.
+/// Frame time and input are fed in from outside so it can be stepped deterministically
+/// in tests and on machines without a display.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Simulation {
+    pub game: GameState,
+    pub global: GlobalState,
+    /// Where the player starts and gets teleported to on reset.
+    pub(crate) spawn: Vec2,
+    /// Walls, the same for every tick. Empty unless set with [`Simulation::set_world`].
+    pub(crate) world: World,
+    /// Seconds since start, sum of all `delta_time`s.
+    pub time: f64,
+    /// Time of the last buffered input sample.
+    last_sample_time: f64,
+    /// When input was last taken for a tick, where the next tick's input starts.
+    last_input_time: f64,
+    last_wish_dir: Vec2,
+    /// Every tick gets recorded here while `Some`.
+    pub recording: Option<Recording>,
+    /// Ticks use this instead of the buffered input while `Some`.
+    pub playback: Option<Playback>,
+    /// Ticks received from a server, see [`Simulation::apply_snapshot`].
+    pub received: TickBuffer<TickState>,
+}
+
+impl Simulation {
+    pub fn new(tps: f32, spawn: Vec2) -> Result<Self, TickSettingsError> {
+        let global = GlobalState::new(tps)?;
+        let game = GameState::new(global.tick_settings.buffer_len, spawn);
+        let received = TickBuffer::new(global.tick_settings.buffer_len);
+        Ok(Self {
+            game,
+            global,
+            spawn,
+            world: World { origin: spawn, ..World::EMPTY },
+            time: 0.0,
+            last_sample_time: 0.0,
+            last_input_time: 0.0,
+            last_wish_dir: Vec2::ZERO,
+            recording: None,
+            playback: None,
+            received,
+        })
+    }
+
+    pub fn spawn(&self) -> Vec2 {
+        self.spawn
+    }
+
+    /// Move spawn, and the world and every buffered tick along with it, so nothing ends up
+    /// somewhere else relative to the walls. Like when the window gets resized.
+    pub fn set_spawn(&mut self, spawn: Vec2) {
+        let offset = spawn - self.spawn;
+        if offset == Vec2::ZERO {
+            return;
+        }
+        self.spawn = spawn;
+        self.world.origin = spawn;
+        self.game.translate(offset);
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /// Use `world` from now on, placed around spawn. Not recorded, replays need the same.
+    pub fn set_world(&mut self, world: World) {
+        self.world = World { origin: self.spawn, ..world };
+    }
+
+    /// Advance the clock by `delta_time` seconds. Returns how many ticks are due,
+    /// can be more than 1 when tps > fps. None are due while updates are paused,
+    /// they're counted in `missed_ticks` instead. The clock is frozen while paused.
+    pub fn advance_clock(&mut self, delta_time: f32) -> usize {
+        if self.global.tick_control.paused {
+            return 0;
+        }
+        self.time += delta_time as f64;
+        let ticks_due = self.global.update_accumulator.add(delta_time);
+        if self.global.pause_updates {
+            self.global.missed_ticks += ticks_due;
+            return 0;
+        }
+        ticks_due
+    }
+
+    /// Read `source` and buffer the sample until the next tick, if there was one.
+    /// Sets the sample's duration to the time since the previous sample.
+    pub fn poll_input(&mut self, source: &mut dyn InputSource) {
+        // TODO: possibly limit to like 10,000 or 80,000 or smth
+        // incase lag or mega input
+        if let Some(mut sample) = source.sample(self.time) {
+            sample.duration = (sample.time - self.last_sample_time).max(0.0) as f32;
+            self.last_sample_time = sample.time;
+            self.global.input_buffer.push(sample);
+        }
+    }
+
+    /// Average the buffered input for the next tick without stepping, like when a server
+    /// does the stepping. Clears the input buffer.
+    /// If nothing was buffered the last tick's input is reused.
+    /// The first shot fired is kept, with when it was fired since the last time input was
+    /// taken, as a fraction of the tick.
+    pub fn take_input(&mut self, reset: bool) -> TickInput {
+        let buffer = &self.global.input_buffer;
+        let wish_dir = if buffer.is_empty() {
+            self.last_wish_dir
+        } else {
+            self.global.avg_strategy.average(buffer)
+        };
+        let span = self.time - self.last_input_time;
+        let fire = buffer.iter().find_map(|sample| {
+            let fraction =
+                if span > 0.0 { (sample.time - self.last_input_time) / span } else { 0.0 };
+            Some(Fire::new(sample.fire?, fraction as f32))
+        });
+        self.last_wish_dir = wish_dir;
+        self.last_input_time = self.time;
+        self.global.input_buffer.clear();
+        TickInput { wish_dir, reset, fire }
+    }
+
+    /// Use a tick stepped somewhere else, like on the server, as the current tick.
+    /// Ticks that never arrived are guessed from the closest ones that did, and replaced
+    /// if they arrive late. Ticks older than the buffer are ignored.
+    pub fn apply_snapshot(&mut self, snapshot: &Snapshot) {
+        self.set_spawn(snapshot.spawn);
+        if snapshot.settings != self.global.tick_settings {
+            self.global.set_tick_settings(&mut self.game, snapshot.settings.clone());
+            self.received.set_span(self.global.tick_settings.buffer_len);
+        }
+        let tick_number = snapshot.tick_number;
+        if !self.received.insert(tick_number, snapshot.state.clone()) {
+            return;
+        }
+        if tick_number > self.game.tick_number {
+            self.game.push_tick(tick_number, snapshot.state.clone());
+            self.global.missed_ticks = 0;
+        } else if let Ok(tick) = self.game.get_tick_mut(tick_number) {
+            *tick = snapshot.state.clone();
+        } else {
+            return;
+        }
+
+        let before = tick_number.checked_sub(1).and_then(|n| self.received.neighbours(n).0);
+        let (_, after) = self.received.neighbours(tick_number + 1);
+        let received = (tick_number, &snapshot.state);
+        for (a, b) in [before.map(|a| (a, received)), after.map(|b| (received, b))]
+            .into_iter()
+            .flatten()
+        {
+            Self::fill_gap(&mut self.game, a, b);
+        }
+    }
+
+    /// Guess the ticks between 2 received ticks, so they don't stand still and then jump.
+    /// Entities spawned or despawned in between are left as they were in `a`.
+    fn fill_gap(
+        game: &mut GameState, (a, a_tick): (TickNumber, &TickState),
+        (b, b_tick): (TickNumber, &TickState),
+    ) {
+        let ticks = b.since(a).unwrap_or(0);
+        let gap = ticks as f32;
+        for i in 1..ticks {
+            let Ok(tick) = game.get_tick_mut(a + i) else {
+                continue;
+            };
+            *tick = a_tick.clone();
+            let t = i as f32 / gap;
+            for (id, entity) in tick.entities.iter_mut() {
+                let Some(b_entity) = b_tick.entities.get(id) else {
+                    continue;
+                };
+                let (a_mov, b_mov) = (entity.movement.clone(), &b_entity.movement);
+                // vel is per tick, the gap is several ticks long
+                let movement = &mut entity.movement;
+                movement.pos = hermite2(a_mov.pos, a_mov.vel * gap, b_mov.pos, b_mov.vel * gap, t);
+                movement.vel = lerp_precise2(a_mov.vel, b_mov.vel, t);
+                movement.accel = lerp_precise2(a_mov.accel, b_mov.accel, t);
+            }
+        }
+    }
+
+    /// Average the buffered input and step exactly one tick. Clears the input buffer.
+    /// If nothing was buffered, like the 2nd tick in a frame, the last tick's input is reused.
+    /// While playing back, the recorded input is used instead of the buffered input and `reset`.
+    /// Returns the input the tick was stepped with.
+    pub fn tick(&mut self, reset: bool) -> TickInput {
+        let mut input = self.take_input(reset);
+        if let Some(record) = self.playback.as_mut().and_then(|playback| playback.next()) {
+            input = record.input;
+            self.set_spawn(record.spawn);
+            if record.settings != self.global.tick_settings {
+                self.global.set_tick_settings(&mut self.game, record.settings);
+            }
+        }
+        if self.playback.as_ref().is_some_and(|playback| playback.is_finished()) {
+            self.playback = None;
+        }
+        self.tick_with(input)
+    }
+
+    /// Step exactly one tick with `input`, ignoring the input buffer and playback.
+    pub fn tick_with(&mut self, input: TickInput) -> TickInput {
+        self.game.update(&self.global.tick_settings, &input, self.spawn, &self.world);
+        self.global.missed_ticks = 0;
+
+        if let Some(recording) = &mut self.recording {
+            let settings = self.global.tick_settings.clone();
+            recording.push(TickRecord { input, settings, spawn: self.spawn });
+        }
+        input
+    }
+
+    /// Freeze the clock and ticks, or unfreeze and go back to looking at the current tick.
+    pub fn toggle_pause(&mut self) {
+        let tick_progress = self.global.tick_progress();
+        let control = &mut self.global.tick_control;
+        control.paused = !control.paused;
+        // start looking at what was on screen
+        control.view_back = if control.paused { 1.0 - tick_progress } else { 0.0 };
+    }
+
+    /// While paused, look one tick forward, or run exactly one tick if already at the
+    /// current tick.
+    pub fn step_forward(&mut self) {
+        let control = &mut self.global.tick_control;
+        if !control.paused {
+            return;
+        }
+        if control.view_back > 0.0 {
+            control.view_back = (control.view_back.ceil() - 1.0).max(0.0);
+        } else {
+            self.tick(false);
+        }
+    }
+
+    /// While paused, look one tick further back.
+    pub fn step_back(&mut self) {
+        let view_back = self.global.tick_control.view_back.floor() + 1.0;
+        self.scrub_to(view_back);
+    }
+
+    /// While paused, move the view by `ticks`, positive is back in time.
+    pub fn scrub(&mut self, ticks: f32) {
+        let view_back = self.global.tick_control.view_back + ticks;
+        self.scrub_to(view_back);
+    }
+
+    fn scrub_to(&mut self, view_back: f32) {
+        let control = &mut self.global.tick_control;
+        if control.paused {
+            let max = self.game.buffer.len().saturating_sub(2) as f32;
+            control.view_back = view_back.clamp(0.0, max);
+        }
+    }
+
+    /// Throw away every tick newer than the one being looked at and continue from there.
+    pub fn resume_from_view(&mut self) {
+        let control = &mut self.global.tick_control;
+        let ticks = control.view_back.round() as usize;
+        *control = TickControl::default();
+        self.game.rewind(ticks);
+        self.global.input_buffer.clear();
+        // newer ticks get made again, differently
+        self.received.clear();
+    }
+
+    /// Start recording from the current tick. Restarts if already recording.
+    pub fn start_recording(&mut self) {
+        let recording = Recording::new(
+            self.game.tick_number,
+            self.game.current_tick().clone(),
+            self.global.tick_settings.clone(),
+            self.spawn,
+        );
+        self.recording = Some(recording);
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+
+    /// Rewind to the start of `recording` and play it back through the following ticks.
+    pub fn start_playback(&mut self, recording: Recording) {
+        let buffer_len = recording.start_settings.buffer_len;
+        self.game = GameState::with_first_tick(buffer_len, &recording.start_state);
+        self.game.tick_number = recording.start_tick_number;
+        self.global.set_tick_settings(&mut self.game, recording.start_settings.clone());
+        // the start state is already around it
+        self.spawn = recording.start_spawn;
+        self.world.origin = recording.start_spawn;
+
+        self.playback = Some(Playback::new(recording)).filter(|playback| !playback.is_finished());
+    }
+
+    /// Give back memory left over from shrinking buffers, like after lowering tps.
+    pub fn compact(&mut self) {
+        self.game.compact();
+        self.received.compact();
+        self.global.input_buffer.shrink_to_fit();
+    }
+
+    /// Run one frame: advance the clock, poll `source` and run all the ticks due.
+    /// Returns how many ticks were stepped.
+    pub fn frame(&mut self, delta_time: f32, source: &mut dyn InputSource, reset: bool) -> usize {
+        let ticks_due = self.advance_clock(delta_time);
+        self.poll_input(source);
+        for _ in 0..ticks_due {
+            self.tick(reset);
+        }
+        ticks_due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{Entity, EntityKind};
+    use crate::input::InputSample;
+    use crate::player::Player;
+
+    const SPAWN: Vec2 = Vec2::new(400.0, 300.0);
+
+    fn run(sim: &mut Simulation, frames: usize, fps: f32, dir: Vec2) -> usize {
+        (0..frames).map(|_| sim.frame(fps.recip(), &mut |_| dir, false)).sum()
+    }
+
+    #[test]
+    fn headless_ticks() {
+        let mut sim = Simulation::new(30.0, SPAWN).unwrap();
+        let ticks = run(&mut sim, 6000, 60.0, Vec2::X);
+
+        assert_eq!(ticks, sim.game.tick_number.0);
+        // 100 secs at 30 tps, give or take float error
+        assert!((2990..=3010).contains(&ticks), "{}", ticks);
+        assert!(sim.game.current_tick().player().movement.pos.x > SPAWN.x);
+        assert_eq!(sim.game.current_tick().player().movement.pos.y, SPAWN.y);
+    }
+
+    #[test]
+    fn arena() {
+        let mut sim = Simulation::new(30.0, SPAWN).unwrap();
+        sim.set_world(World::arena());
+        run(&mut sim, 600, 60.0, Vec2::X);
+
+        // ran into the right wall of the arena and stayed there
+        let player = &sim.game.current_tick().player().movement;
+        let wall = SPAWN.x + 390.0 - Player::PLAYER_SIZE;
+        assert!((wall - 1.0..=wall).contains(&player.pos.x), "{}", player.pos);
+        assert_eq!(player.pos.y, SPAWN.y);
+        assert_eq!(player.vel.x, 0.0);
+
+        // walls and everything else follow spawn, nothing ends up on the other side of a wall
+        let offset = Vec2::new(1000.0, 0.0);
+        let before = sim.game.clone();
+        sim.set_spawn(SPAWN + offset);
+        assert_eq!(sim.world().origin, sim.spawn());
+        for (tick, moved) in before.buffer().iter().zip(sim.game.buffer().iter()) {
+            for ((_, entity), (_, moved)) in tick.entities.iter().zip(moved.entities.iter()) {
+                assert_eq!(moved.movement.pos, entity.movement.pos + offset);
+            }
+        }
+        run(&mut sim, 60, 60.0, Vec2::X);
+        let player = &sim.game.current_tick().player().movement;
+        assert!((wall + 999.0..=wall + 1000.0).contains(&player.pos.x), "{}", player.pos);
+        // and resetting never lands outside them
+        sim.tick(true);
+        run(&mut sim, 600, 60.0, Vec2::X);
+        let player = &sim.game.current_tick().player().movement;
+        assert!((wall + 999.0..=wall + 1000.0).contains(&player.pos.x), "{}", player.pos);
+    }
+
+    #[test]
+    fn tps_above_fps() {
+        let mut sim = Simulation::new(120.0, SPAWN).unwrap();
+        let ticks = run(&mut sim, 300, 30.0, Vec2::X);
+        // 10 secs at 120 tps, not slowed down to 30 tps
+        assert!((1195..=1205).contains(&ticks), "{}", ticks);
+        assert_eq!(sim.global.update_accumulator.dropped_ticks, 0);
+        // kept moving on the ticks without new input
+        assert_eq!(
+            sim.game.current_tick().player().movement.accel.x,
+            sim.game.prev_tick().player().movement.accel.x
+        );
+        assert!(sim.game.current_tick().player().movement.accel.x > 0.0);
+    }
+
+    #[test]
+    fn refused_settings() {
+        assert_eq!(Simulation::new(5.0, SPAWN).unwrap_err(), TickSettingsError::TpsTooLow(5.0));
+        assert!(matches!(Simulation::new(f32::NAN, SPAWN), Err(TickSettingsError::TpsTooLow(_))));
+        assert!(matches!(
+            Simulation::new(f32::INFINITY, SPAWN),
+            Err(TickSettingsError::BadSpeedFactor(_))
+        ));
+
+        let mut sim = Simulation::new(30.0, SPAWN).unwrap();
+        let before = sim.global.tick_settings.clone();
+        let short = TickSettingsError::BufferTooShort { secs: 0.03, len: 1 };
+        assert_eq!(sim.global.set_buffer_secs(&mut sim.game, 0.03), Err(short));
+        assert_eq!(sim.global.set_tps(&mut sim.game, 9.0), Err(TickSettingsError::TpsTooLow(9.0)));
+        // nothing changed
+        assert_eq!(sim.global.tick_settings, before);
+        assert_eq!(sim.game.buffer().len(), before.buffer_len);
+        assert_eq!(short.to_string(), "buffer of 0.03s only holds 1 ticks, needs at least 2");
+
+        let err = sim.global.set_interp_delay(-1.0).unwrap_err();
+        assert_eq!(err.max, sim.global.max_interp_delay());
+    }
+
+    #[test]
+    fn pause_step_rewind() {
+        let mut sim = Simulation::new(30.0, SPAWN).unwrap();
+        run(&mut sim, 60, 60.0, Vec2::X);
+        let tick_number = sim.game.tick_number;
+
+        sim.toggle_pause();
+        assert_eq!(run(&mut sim, 60, 60.0, Vec2::X), 0);
+        // look at the current tick, then run 1
+        sim.step_forward();
+        assert_eq!(sim.global.tick_control.view_back, 0.0);
+        sim.step_forward();
+        assert_eq!(sim.game.tick_number, tick_number + 1);
+
+        let older = sim.game.get_prev_tick(3).unwrap().clone();
+        sim.step_back();
+        sim.step_back();
+        sim.scrub(0.5);
+        sim.step_back();
+        assert_eq!(sim.global.tick_control.view_back, 3.0);
+        assert_eq!(sim.game.view_back(3.0), (3, 1.0));
+        sim.scrub(1000.0);
+        assert_eq!(sim.global.tick_control.view_back, sim.game.buffer().len() as f32 - 2.0);
+        sim.scrub(3.0 - sim.global.tick_control.view_back);
+        sim.step_forward();
+        assert_eq!(sim.global.tick_control.view_back, 2.0);
+        sim.step_back();
+
+        let buffer_len = sim.game.buffer().len();
+        sim.resume_from_view();
+        assert_eq!(sim.game.tick_number, tick_number.saturating_sub(2));
+        assert_eq!(*sim.game.current_tick(), older);
+        assert_eq!(sim.game.buffer().len(), buffer_len);
+        assert!(run(&mut sim, 60, 60.0, Vec2::X) > 0);
+    }
+
+    #[test]
+    fn lost_and_late_snapshots() {
+        let mut server = Simulation::new(30.0, SPAWN).unwrap();
+        let mut client = Simulation::new(30.0, SPAWN).unwrap();
+        let mut snapshots = Vec::new();
+        for _ in 0..6 {
+            server.tick_with(TickInput { wish_dir: Vec2::X, reset: false, ..Default::default() });
+            snapshots.push(Snapshot::new(&server, 0));
+        }
+        let pos = |sim: &Simulation, tick| {
+            sim.game.get_tick(TickNumber(tick)).unwrap().player().movement.pos
+        };
+
+        // 3 and 4 get lost
+        for snapshot in
+            snapshots.iter().filter(|snapshot| ![3, 4].contains(&snapshot.tick_number.0))
+        {
+            client.apply_snapshot(snapshot);
+        }
+        assert_eq!(client.game.tick_number, TickNumber(6));
+        assert_eq!(client.received.gaps().next(), Some(TickNumber(3)..TickNumber(5)));
+        // guessed, still moving instead of standing still
+        assert!(pos(&client, 2).x < pos(&client, 3).x);
+        assert!(pos(&client, 3).x < pos(&client, 4).x);
+        assert!(pos(&client, 4).x < pos(&client, 5).x);
+        assert!(pos(&client, 3).distance(pos(&server, 3)) < 2.0);
+
+        // 4 arrives late and replaces the guess
+        client.apply_snapshot(&snapshots[3]);
+        assert_eq!(client.received.missing(), 1);
+        assert_eq!(client.game.tick_number, TickNumber(6));
+        assert_eq!(client.game.get_tick(TickNumber(4)), server.game.get_tick(TickNumber(4)));
+        assert_eq!(client.game.current_tick(), server.game.current_tick());
+    }
+
+    #[test]
+    fn deterministic() {
+        // same input, very different frame times, the same ticks
+        let dir = Vec2::new(0.6, -0.8);
+        let mut a = Simulation::new(60.0, SPAWN).unwrap();
+        while a.game.tick_number.0 < 2000 {
+            a.frame(1.0 / 144.0, &mut |_| dir, false);
+        }
+        let mut b = Simulation::new(60.0, SPAWN).unwrap();
+        // uneven, with the odd hitch running several ticks in 1 frame
+        let frames = [1.0 / 37.0, 1.0 / 240.0, 0.1, 1.0 / 61.0];
+        for delta_time in frames.iter().cycle() {
+            if b.game.tick_number >= a.game.tick_number {
+                break;
+            }
+            b.frame(*delta_time, &mut |_| dir, false);
+        }
+
+        let tick_number = TickNumber(2000);
+        let tick = a.game.get_tick(tick_number).unwrap();
+        assert_eq!(tick, b.game.get_tick(tick_number).unwrap());
+        assert!(tick.player().movement.pos.distance(SPAWN) > 100.0);
+    }
+
+    #[test]
+    fn replay_bit_for_bit() {
+        let mut sim = Simulation::new(30.0, SPAWN).unwrap();
+        run(&mut sim, 50, 60.0, Vec2::X);
+
+        sim.start_recording();
+        let mut bot = |time: f64| Vec2::from_angle(time as f32 * 5.0) * 0.7;
+        for i in 0..2000 {
+            if i == 500 {
+                sim.global.set_tps(&mut sim.game, 45.0).unwrap();
+            }
+            if i == 1000 {
+                sim.set_spawn(Vec2::new(10.0, 20.0));
+            }
+            sim.frame(1.0 / 144.0, &mut bot, i % 700 == 0);
+        }
+        let recording = sim.stop_recording().unwrap();
+        let end = sim.game.current_tick().clone();
+        let end_tick_number = sim.game.tick_number;
+
+        let mut file = Vec::new();
+        recording.write(&mut file).unwrap();
+        let loaded = Recording::read(&mut file.as_slice()).unwrap();
+        assert_eq!(recording, loaded);
+
+        let mut replay = Simulation::new(60.0, Vec2::ZERO).unwrap();
+        replay.start_playback(loaded);
+        while replay.playback.is_some() {
+            replay.tick(false);
+        }
+        assert_eq!(replay.game.tick_number, end_tick_number);
+        assert_eq!(*replay.game.current_tick(), end);
+        assert_eq!(replay.global.tick_settings, sim.global.tick_settings);
+    }
+
+    #[test]
+    fn reset_to_spawn() {
+        let mut sim = Simulation::new(30.0, SPAWN).unwrap();
+        run(&mut sim, 100, 60.0, Vec2::Y);
+        assert_ne!(sim.game.current_tick().player().movement.pos, SPAWN);
+
+        sim.tick(true);
+        let movement = &sim.game.current_tick().player().movement;
+        assert_eq!(movement.pos, SPAWN);
+        assert_eq!(movement.vel, Vec2::ZERO);
+    }
+
+    #[test]
+    fn entities() {
+        let mut sim = Simulation::new(30.0, SPAWN).unwrap();
+        assert_eq!(sim.game.current_tick().entities.len(), 3);
+
+        // npcs circle spawn on their own
+        run(&mut sim, 300, 60.0, Vec2::ZERO);
+        for (_, npc) in sim.game.current_tick().entities.iter().skip(1) {
+            let orbit = npc.movement.pos.distance(SPAWN);
+            assert!((120.0..180.0).contains(&orbit), "{}", orbit);
+        }
+
+        // gone from the tick it despawned in on, still there in older ticks
+        let npc = sim.game.current_tick_mut().entities.spawn(Entity::new(EntityKind::Npc, SPAWN));
+        sim.tick_with(TickInput::default());
+        sim.game.current_tick_mut().entities.despawn(npc);
+        sim.tick_with(TickInput::default());
+        assert!(!sim.game.current_tick().entities.contains(npc));
+        assert!(!sim.game.get_prev_tick(1).unwrap().entities.contains(npc));
+        assert!(sim.game.get_prev_tick(2).unwrap().entities.contains(npc));
+        // and its id doesn't come back
+        let next = sim.game.current_tick_mut().entities.spawn(Entity::default());
+        assert!(next > npc);
+    }
+
+    /// Moves right and fires once at `at` seconds.
+    struct Shooter {
+        at: f64,
+        target: Vec2,
+    }
+
+    impl InputSource for Shooter {
+        fn sample(&mut self, time: f64) -> Option<InputSample> {
+            let sample = InputSample::new(Vec2::X, time);
+            if time < self.at {
+                return Some(sample);
+            }
+            self.at = f64::INFINITY;
+            Some(sample.with_fire(self.target))
+        }
+    }
+
+    #[test]
+    fn projectiles() {
+        let mut sim = Simulation::new(30.0, SPAWN).unwrap();
+        sim.start_recording();
+        // halfway between 2 ticks
+        let mut shooter = Shooter { at: 1.0 + 1.5 / 120.0, target: SPAWN - Vec2::new(0.0, 1000.0) };
+        while sim.game.current_tick().entities.len() == 3 {
+            sim.frame(1.0 / 120.0, &mut shooter, false);
+        }
+
+        let (tick, prev) = (sim.game.current_tick(), sim.game.prev_tick());
+        let (id, projectile) = tick.entities.iter().next_back().unwrap();
+        assert!(!prev.entities.contains(id));
+        let EntityKind::Projectile { ticks_left, subtick } = projectile.kind else {
+            panic!("{:?}", projectile);
+        };
+        // fired a few frames into the tick
+        assert!((32..=224).contains(&subtick), "{}", subtick);
+
+        // drawn from right where it was fired instead of where it got to by the tick
+        let fraction = subtick as f32 / 256.0;
+        assert!(projectile.spawn_pos(fraction - 0.01).is_none());
+        let shooter_pos = prev.player().movement.pos.lerp(tick.player().movement.pos, fraction);
+        let fired_from = projectile.spawn_pos(fraction).unwrap();
+        let muzzle = Player::PLAYER_SIZE + Entity::PROJECTILE_SIZE;
+        assert!((fired_from.distance(shooter_pos) - muzzle).abs() < 1e-3);
+        assert!(fired_from.y < shooter_pos.y);
+        assert_eq!(projectile.spawn_pos(1.0), Some(projectile.movement.pos));
+
+        // gone after its lifetime
+        for _ in 1..ticks_left {
+            sim.tick(false);
+        }
+        assert!(sim.game.current_tick().entities.contains(id));
+        sim.tick(false);
+        assert!(!sim.game.current_tick().entities.contains(id));
+        assert!(sim.game.prev_tick().entities.contains(id));
+
+        // and replays the same
+        let end = sim.game.current_tick().clone();
+        let mut file = Vec::new();
+        sim.stop_recording().unwrap().write(&mut file).unwrap();
+        let mut replay = Simulation::new(30.0, SPAWN).unwrap();
+        replay.start_playback(Recording::read(&mut file.as_slice()).unwrap());
+        while replay.playback.is_some() {
+            replay.tick(false);
+        }
+        assert_eq!(*replay.game.current_tick(), end);
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+
+use super::{ring_buffer, TickNumber};
+
+/// Ticks keyed by tick number, only the ones that actually arrived.
+/// Unlike [`RingBuffer`](super::ring_buffer::RingBuffer) ticks can be missing, like when
+/// packets get lost, and can arrive out of order.
+///
+/// Keeps every tick up to `span` ticks older than the newest, sorted oldest first.
+/// Lookups are a binary search.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct TickBuffer<T> {
+    ticks: VecDeque<(TickNumber, T)>,
+    span: usize,
+}
+
+impl<T> TickBuffer<T> {
+    pub fn new(span: usize) -> Self {
+        Self { ticks: VecDeque::with_capacity(span), span }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.ticks.clear();
+    }
+
+    /// Reallocate to fit exactly, see [`RingBuffer::compact`](ring_buffer::RingBuffer::compact).
+    pub fn compact(&mut self) {
+        ring_buffer::compact(&mut self.ticks);
+    }
+
+    /// How many tick numbers back from the newest are kept.
+    pub fn span(&self) -> usize {
+        self.span
+    }
+
+    pub fn set_span(&mut self, span: usize) {
+        self.span = span;
+        self.drop_old();
+    }
+
+    /// Oldest tick number still kept, ticks before this get dropped.
+    fn oldest_kept(&self) -> TickNumber {
+        self.newest()
+            .map_or(TickNumber(0), |(newest, _)| newest.next().saturating_sub(self.span))
+    }
+
+    fn drop_old(&mut self) {
+        let oldest = self.oldest_kept();
+        while self.ticks.front().is_some_and(|(tick, _)| *tick < oldest) {
+            self.ticks.pop_front();
+        }
+    }
+
+    fn search(&self, tick_number: TickNumber) -> Result<usize, usize> {
+        self.ticks.binary_search_by_key(&tick_number, |(tick, _)| *tick)
+    }
+
+    /// Store a tick, replacing one with the same number. Returns false if it was too old
+    /// to keep.
+    pub fn insert(&mut self, tick_number: TickNumber, value: T) -> bool {
+        if tick_number < self.oldest_kept() {
+            return false;
+        }
+        match self.search(tick_number) {
+            Ok(i) => self.ticks[i].1 = value,
+            Err(i) => self.ticks.insert(i, (tick_number, value)),
+        }
+        self.drop_old();
+        true
+    }
+
+    pub fn get(&self, tick_number: TickNumber) -> Option<&T> {
+        self.search(tick_number).ok().map(|i| &self.ticks[i].1)
+    }
+
+    pub fn get_mut(&mut self, tick_number: TickNumber) -> Option<&mut T> {
+        self.search(tick_number).ok().map(|i| &mut self.ticks[i].1)
+    }
+
+    pub fn contains(&self, tick_number: TickNumber) -> bool {
+        self.search(tick_number).is_ok()
+    }
+
+    pub fn newest(&self) -> Option<(TickNumber, &T)> {
+        self.ticks.back().map(|(tick, value)| (*tick, value))
+    }
+
+    pub fn oldest(&self) -> Option<(TickNumber, &T)> {
+        self.ticks.front().map(|(tick, value)| (*tick, value))
+    }
+
+    /// The closest stored ticks at or before and after `tick_number`.
+    /// If `tick_number` itself is stored it's the first one.
+    #[allow(clippy::type_complexity)]
+    pub fn neighbours(
+        &self, tick_number: TickNumber,
+    ) -> (Option<(TickNumber, &T)>, Option<(TickNumber, &T)>) {
+        let (before, after) = match self.search(tick_number) {
+            Ok(i) => (Some(i), i + 1),
+            Err(i) => (i.checked_sub(1), i),
+        };
+        let get = |i: usize| self.ticks.get(i).map(|(tick, value)| (*tick, value));
+        (before.and_then(get), get(after))
+    }
+
+    /// Ranges of tick numbers missing between the oldest and newest stored tick.
+    pub fn gaps(&self) -> impl Iterator<Item = Range<TickNumber>> + '_ {
+        self.ticks
+            .iter()
+            .zip(self.ticks.iter().skip(1))
+            .filter(|((a, _), (b, _))| a.next() < *b)
+            .map(|((a, _), (b, _))| a.next()..*b)
+    }
+
+    /// How many ticks are missing between the oldest and newest stored tick.
+    pub fn missing(&self) -> usize {
+        self.gaps().filter_map(|gap| gap.end.since(gap.start)).sum()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (TickNumber, &T)> {
+        self.ticks.iter().map(|(tick, value)| (*tick, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_buffer() {
+        let t = TickNumber;
+        let numbers =
+            |buffer: &TickBuffer<usize>| buffer.iter().map(|(t, _)| t.0).collect::<Vec<_>>();
+        let mut buffer = TickBuffer::new(8);
+        for tick in [3, 1, 4, 5, 9, 2, 6] {
+            assert!(buffer.insert(t(tick), tick * 10));
+        }
+        assert_eq!(numbers(&buffer), [2, 3, 4, 5, 6, 9]);
+        assert_eq!(buffer.gaps().next(), Some(t(7)..t(9)));
+        assert_eq!(buffer.missing(), 2);
+
+        assert_eq!(buffer.get(t(4)), Some(&40));
+        assert_eq!(buffer.get(t(7)), None);
+        assert_eq!(buffer.neighbours(t(7)), (Some((t(6), &60)), Some((t(9), &90))));
+        assert_eq!(buffer.neighbours(t(6)), (Some((t(6), &60)), Some((t(9), &90))));
+        assert_eq!(buffer.neighbours(t(0)), (None, Some((t(2), &20))));
+        assert_eq!(buffer.neighbours(t(10)), (Some((t(9), &90)), None));
+
+        // late tick fills the gap, too old ticks are dropped
+        buffer.insert(t(8), 80);
+        assert_eq!(buffer.gaps().next(), Some(t(7)..t(8)));
+        assert!(!buffer.insert(t(1), 10));
+        buffer.insert(t(12), 120);
+        assert_eq!(buffer.oldest(), Some((t(5), &50)));
+        assert_eq!(buffer.newest(), Some((t(12), &120)));
+
+        buffer.set_span(2);
+        assert_eq!(numbers(&buffer), [12]);
+    }
+}
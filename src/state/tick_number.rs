@@ -0,0 +1,117 @@
+use std::fmt::Display;
+use std::ops::{Add, AddAssign};
+
+/// Which tick, counting up by 1 every tick since the game started.
+/// Only ever compared or stepped, so it can't get mixed up with how many ticks back
+/// something is in a buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct TickNumber(pub usize);
+
+impl TickNumber {
+    pub fn next(self) -> Self {
+        Self(self.0 + 1)
+    }
+
+    /// The tick `ticks` before this one, `None` if that's before the first tick.
+    pub fn checked_sub(self, ticks: usize) -> Option<Self> {
+        self.0.checked_sub(ticks).map(Self)
+    }
+
+    /// The tick `ticks` before this one, or the first tick.
+    pub fn saturating_sub(self, ticks: usize) -> Self {
+        Self(self.0.saturating_sub(ticks))
+    }
+
+    /// How many ticks after `earlier` this is, `None` if it's before `earlier`.
+    pub fn since(self, earlier: Self) -> Option<usize> {
+        self.0.checked_sub(earlier.0)
+    }
+}
+
+impl Add<usize> for TickNumber {
+    type Output = Self;
+
+    fn add(self, ticks: usize) -> Self {
+        Self(self.0 + ticks)
+    }
+}
+
+impl AddAssign<usize> for TickNumber {
+    fn add_assign(&mut self, ticks: usize) {
+        self.0 += ticks;
+    }
+}
+
+impl Display for TickNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Why a tick couldn't be looked up in a [`GameState`](super::GameState).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TickError {
+    /// Newer than the current tick, like a client claiming to see the future.
+    Future { tick: TickNumber, current: TickNumber },
+    /// Further back than the buffer goes.
+    TooOld { back: usize, buffer_len: usize },
+}
+
+impl Display for TickError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TickError::Future { tick, current } => {
+                write!(f, "tick {} is after the current tick {}", tick, current)
+            }
+            TickError::TooOld { back, buffer_len } => {
+                write!(f, "{} ticks back is older than the {} buffered ticks", back, buffer_len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TickError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_number() {
+        let tick = TickNumber(5);
+        assert_eq!(tick.next(), TickNumber(6));
+        assert_eq!(tick + 3, TickNumber(8));
+        assert_eq!(tick.checked_sub(6), None);
+        assert_eq!(tick.saturating_sub(6), TickNumber(0));
+        assert_eq!(TickNumber(8).since(tick), Some(3));
+        assert_eq!(tick.since(TickNumber(8)), None);
+    }
+
+    #[test]
+    fn lookups() {
+        use crate::state::GameState;
+        use macroquad::math::Vec2;
+
+        let mut game = GameState::new(4, Vec2::ZERO);
+        for _ in 0..10 {
+            game.advance_tick();
+        }
+        assert!(game.get_tick(TickNumber(7)).is_ok());
+        let current = TickNumber(10);
+        let future = TickError::Future { tick: TickNumber(11), current };
+        assert_eq!(game.get_tick(TickNumber(11)), Err(future));
+        let too_old = TickError::TooOld { back: 4, buffer_len: 4 };
+        assert_eq!(game.get_tick(TickNumber(6)), Err(too_old));
+        assert_eq!(
+            game.get_prev_tick(usize::MAX).map(|_| ()).unwrap_err().to_string(),
+            format!("{} ticks back is older than the 4 buffered ticks", usize::MAX)
+        );
+
+        // never shrinks past having a previous tick
+        game.resize_buffer(0);
+        assert_eq!(game.buffer().len(), GameState::MIN_BUFFER_LEN);
+        assert_eq!(game.prev_tick(), game.get_prev_tick(1).unwrap());
+        game.push_tick(TickNumber(20), game.current_tick().clone());
+        assert_eq!(game.tick_number, TickNumber(20));
+    }
+}
@@ -0,0 +1,309 @@
+//! Run the [`Simulation`] on its own thread at a fixed tps, separate from drawing.
+//!
+//! The update thread owns the simulation and puts every finished tick in a bounded queue,
+//! dropping the oldest when it's full. The draw thread takes them out into its own copy of
+//! the tick buffer, and works out how far into the next tick it is from a [`Clock`] both
+//! threads share. Neither ever waits for the other, so fps and tps don't affect each other
+//! at all.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, JoinHandle};
+use std::time::Duration;
+
+use macroquad::math::Vec2;
+
+use crate::input::{ChannelInput, InputSample};
+use crate::net::Snapshot;
+use crate::state::{Simulation, TickNumber, TickSettings};
+use crate::time::Clock;
+
+/// A finished tick and when it was due on the shared clock.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublishedTick {
+    pub snapshot: Snapshot,
+    pub due: f64,
+}
+
+/// Changes from the draw thread, applied before the next tick.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Settings(TickSettings),
+    /// If reset is held down.
+    Reset(bool),
+    /// See [`Simulation::compact`].
+    Compact,
+    /// Stop or start ticking, see [`Simulation::toggle_pause`].
+    Pause(bool),
+    /// See [`GlobalState::pause_updates`](crate::state::GlobalState::pause_updates).
+    PauseUpdates(bool),
+    /// Run exactly 1 tick while paused.
+    Step,
+    /// Throw away every tick after this one and continue from it,
+    /// see [`Simulation::resume_from_view`].
+    ResumeFrom(TickNumber),
+    /// See [`Simulation::set_spawn`].
+    Spawn(Vec2),
+}
+
+/// Handle to the update thread, lives on the draw thread. Stops the thread when dropped.
+#[derive(Debug)]
+pub struct UpdateThread {
+    pub clock: Clock,
+    /// Shared with the update thread, newest in back.
+    queue: Arc<Mutex<VecDeque<PublishedTick>>>,
+    /// Swapped with `queue` to take everything out without holding the lock.
+    received: VecDeque<PublishedTick>,
+    input: Sender<InputSample>,
+    commands: Sender<Command>,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    /// When the newest received tick was due.
+    last_due: f64,
+    reset: bool,
+    spawn: Vec2,
+}
+
+impl UpdateThread {
+    /// Most ticks waiting to be drawn. If the draw thread falls this far behind the oldest
+    /// get dropped, the newest always make it and the dropped ones are guessed.
+    pub const QUEUE_LEN: usize = 64;
+    /// How often input and commands are checked, well under a tick.
+    const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+    /// Start ticking `sim` on a new thread, from its current tick and time.
+    pub fn spawn(sim: Simulation) -> Self {
+        let clock = Clock::starting_at(sim.time);
+        let spawn = sim.spawn;
+        let queue = Arc::new(Mutex::new(VecDeque::with_capacity(Self::QUEUE_LEN)));
+        let thread_queue = queue.clone();
+        let (input, input_rx) = channel();
+        let (commands, command_rx) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let handle = std::thread::Builder::new()
+            .name("update".into())
+            .spawn(move || {
+                run(sim, clock, thread_queue, ChannelInput(input_rx), command_rx, thread_stop)
+            })
+            // NOTE:PANIC: only fails if the OS can't make threads
+            .unwrap();
+        Self {
+            clock,
+            queue,
+            received: VecDeque::with_capacity(Self::QUEUE_LEN),
+            input,
+            commands,
+            stop,
+            handle: Some(handle),
+            last_due: clock.now(),
+            reset: false,
+            spawn,
+        }
+    }
+
+    /// Queue an input sample for the next tick, `time` should be from [`UpdateThread::clock`].
+    pub fn send_input(&self, sample: InputSample) {
+        // the thread only stops when this is dropped
+        _ = self.input.send(sample);
+    }
+
+    pub fn send(&self, command: Command) {
+        _ = self.commands.send(command);
+    }
+
+    /// Only sends a [`Command::Reset`] when it changes.
+    pub fn set_reset(&mut self, reset: bool) {
+        if reset != self.reset {
+            self.reset = reset;
+            self.send(Command::Reset(reset));
+        }
+    }
+
+    /// Only sends a [`Command::Spawn`] when it changes.
+    pub fn set_spawn(&mut self, spawn: Vec2) {
+        if spawn != self.spawn {
+            self.spawn = spawn;
+            self.send(Command::Spawn(spawn));
+        }
+    }
+
+    /// Move every tick published since the last call into `sim`, at most the newest
+    /// [`UpdateThread::QUEUE_LEN`], and set how far into the next tick it is by the shared
+    /// clock. Returns how many ticks arrived.
+    pub fn receive(&mut self, sim: &mut Simulation) -> usize {
+        // NOTE:PANIC: only poisoned if the update thread panicked while publishing
+        std::mem::swap(&mut *self.queue.lock().unwrap(), &mut self.received);
+        let received = self.received.len();
+        for tick in self.received.drain(..) {
+            sim.apply_snapshot(&tick.snapshot);
+            self.last_due = tick.due;
+        }
+        sim.time = self.clock.now();
+        sim.global.set_time_since_tick((sim.time - self.last_due) as f32);
+        received
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.handle.as_ref().is_some_and(|handle| !handle.is_finished())
+    }
+}
+
+impl Drop for UpdateThread {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            _ = handle.join();
+        }
+    }
+}
+
+fn run(
+    mut sim: Simulation, clock: Clock, queue: Arc<Mutex<VecDeque<PublishedTick>>>,
+    mut input: ChannelInput, commands: Receiver<Command>, stop: Arc<AtomicBool>,
+) {
+    let mut reset = false;
+    let mut last = clock.now();
+    let publish = |sim: &Simulation, due: f64| {
+        let snapshot = Snapshot::new(sim, 0);
+        // NOTE:PANIC: only poisoned if the draw thread panicked while receiving
+        let mut queue = queue.lock().unwrap();
+        if queue.len() >= UpdateThread::QUEUE_LEN {
+            // the draw thread guesses the ones it missed
+            queue.pop_front();
+        }
+        queue.push_back(PublishedTick { snapshot, due });
+    };
+    while !stop.load(Ordering::Relaxed) {
+        for command in commands.try_iter() {
+            match command {
+                Command::Settings(settings) => {
+                    sim.global.set_tick_settings(&mut sim.game, settings)
+                }
+                Command::Reset(held) => reset = held,
+                Command::Compact => sim.compact(),
+                Command::Pause(paused) => sim.global.tick_control.paused = paused,
+                Command::PauseUpdates(paused) => sim.global.pause_updates = paused,
+                Command::Step => {
+                    sim.poll_input(&mut input);
+                    sim.tick(reset);
+                    publish(&sim, clock.now());
+                }
+                Command::ResumeFrom(tick_number) => {
+                    let ticks = sim.game.tick_number.since(tick_number).unwrap_or(0);
+                    sim.global.tick_control.view_back = ticks as f32;
+                    sim.resume_from_view();
+                }
+                Command::Spawn(spawn) => sim.set_spawn(spawn),
+            }
+        }
+
+        let now = clock.now();
+        let ticks_due = sim.advance_clock((now - last) as f32);
+        last = now;
+        sim.poll_input(&mut input);
+        let tick_len = sim.global.tick_settings.tick_len_secs as f64;
+        // the last tick was due when the leftover time started
+        let last_due = now - sim.global.update_accumulator.time as f64;
+        for i in 0..ticks_due {
+            sim.tick(reset);
+            let due = last_due - (ticks_due - 1 - i) as f64 * tick_len;
+            publish(&sim, due);
+        }
+
+        sleep(UpdateThread::POLL_INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::Vec2;
+
+    use super::*;
+
+    #[test]
+    fn handoff() {
+        const SPAWN: Vec2 = Vec2::new(400.0, 300.0);
+        let sim = Simulation::new(120.0, SPAWN).unwrap();
+        let mut drawn = sim.clone();
+        let start = drawn.game.tick_number;
+        let mut thread = UpdateThread::spawn(sim);
+        thread.send_input(InputSample::new(Vec2::X, thread.clock.now()));
+
+        // draw thread is slow, ticks keep coming anyway
+        let mut received = 0;
+        for _ in 0..10 {
+            sleep(Duration::from_millis(20));
+            received += thread.receive(&mut drawn);
+            let progress = drawn.global.tick_progress();
+            assert!((0.0..=1.0).contains(&progress), "{}", progress);
+        }
+        assert!(thread.is_running());
+        assert!(received > 10, "{}", received);
+        assert_eq!(drawn.game.tick_number, start + received);
+        assert!(drawn.game.current_tick().player().movement.pos.x > SPAWN.x);
+
+        // settings go the other way
+        let settings = drawn.global.tick_settings.set_tps(60.0).unwrap();
+        thread.send(Command::Settings(settings.clone()));
+        sleep(Duration::from_millis(50));
+        thread.receive(&mut drawn);
+        assert_eq!(drawn.global.tick_settings, settings);
+
+        drop(thread);
+    }
+
+    #[test]
+    fn tick_control() {
+        const SPAWN: Vec2 = Vec2::new(400.0, 300.0);
+        let sim = Simulation::new(120.0, SPAWN).unwrap();
+        let mut drawn = sim.clone();
+        let mut thread = UpdateThread::spawn(sim);
+        sleep(Duration::from_millis(30));
+
+        // nothing ticks while paused
+        thread.send(Command::Pause(true));
+        sleep(Duration::from_millis(20));
+        thread.receive(&mut drawn);
+        let paused_at = drawn.game.tick_number;
+        sleep(Duration::from_millis(50));
+        assert_eq!(thread.receive(&mut drawn), 0);
+
+        // except stepping exactly 1
+        thread.send(Command::Step);
+        sleep(Duration::from_millis(20));
+        assert_eq!(thread.receive(&mut drawn), 1);
+        assert_eq!(drawn.game.tick_number, paused_at + 1);
+
+        // going back and continuing from there keeps tick numbers the same on both sides
+        let from = paused_at.saturating_sub(2);
+        thread.send(Command::ResumeFrom(from));
+        drawn.global.tick_control.view_back = 3.0;
+        drawn.resume_from_view();
+        assert_eq!(drawn.game.tick_number, from);
+        thread.set_spawn(SPAWN + Vec2::X);
+        sleep(Duration::from_millis(50));
+        let received = thread.receive(&mut drawn);
+        assert!(received > 0);
+        assert_eq!(drawn.game.tick_number, from + received);
+        assert_eq!(drawn.spawn(), SPAWN + Vec2::X);
+    }
+
+    #[test]
+    fn queue_overflow() {
+        let sim = Simulation::new(240.0, Vec2::new(400.0, 300.0)).unwrap();
+        let mut drawn = sim.clone();
+        let mut thread = UpdateThread::spawn(sim);
+
+        // the draw thread stalls for way more than QUEUE_LEN ticks
+        sleep(Duration::from_millis(600));
+        let received = thread.receive(&mut drawn);
+        assert_eq!(received, UpdateThread::QUEUE_LEN);
+        assert!(drawn.game.tick_number.0 > 100, "{:?}", drawn.game.tick_number);
+        // the newest ticks made it, so the draw thread isn't way behind
+        let progress = drawn.global.render_progress();
+        assert!((0.0..2.0).contains(&progress), "{}", progress);
+    }
+}
@@ -2,36 +2,104 @@ use std::time::{Duration, Instant};
 
 use crate::state::TickSettings;
 
-// TODO: better default
-/// Decreasing timer with automatic wrapping and looping.
-#[derive(Clone, Debug, PartialEq, Default)]
-pub struct Timer {
+/// Fixed timestep accumulator. Frame time goes in, whole ticks come out, and the
+/// leftover time is how far between the current and next tick to draw.
+///
+/// Caps how many ticks run per frame so a slow frame can't cause an even slower frame
+/// (spiral of death), the game slows down instead.
+///
+/// See <https://gafferongames.com/post/fix_your_timestep/>
+#[derive(Clone, Debug, PartialEq)]
+pub struct Accumulator {
+    /// Time not yet used up by ticks, 0.0..tick_len after `add()`.
     pub time: f32,
-    pub start_time: f32,
+    pub tick_len: f32,
+    /// Most ticks to run in one frame, excess time is dropped.
+    pub max_ticks_per_frame: usize,
+    /// Ticks due in the last `add()`.
+    pub last_ticks: usize,
+    /// Total ticks dropped because of `max_ticks_per_frame`.
+    pub dropped_ticks: usize,
 }
 
-impl Timer {
-    pub fn new(length: f32) -> Self {
-        Self { time: 0.0, start_time: length }
-    }
+impl Accumulator {
+    pub const DEFAULT_MAX_TICKS_PER_FRAME: usize = 8;
 
-    pub fn decrement(&mut self, time: f32) -> bool {
-        self.time -= time;
+    pub fn new(tick_len: f32) -> Self {
+        Self {
+            time: 0.0,
+            tick_len,
+            max_ticks_per_frame: Self::DEFAULT_MAX_TICKS_PER_FRAME,
+            last_ticks: 0,
+            dropped_ticks: 0,
+        }
+    }
 
-        if self.time <= 0.0 {
-            self.time += self.start_time;
-            return true;
+    /// Add frame time. Returns how many ticks to run now.
+    pub fn add(&mut self, delta_time: f32) -> usize {
+        self.time += delta_time.max(0.0);
+        let due = (self.time / self.tick_len) as usize;
+        self.time = (self.time - due as f32 * self.tick_len).max(0.0);
+        // float error
+        if self.time >= self.tick_len {
+            self.time -= self.tick_len;
+            return self.take(due + 1);
         }
+        self.take(due)
+    }
 
-        false
+    fn take(&mut self, due: usize) -> usize {
+        let ticks = due.min(self.max_ticks_per_frame);
+        self.dropped_ticks += due - ticks;
+        self.last_ticks = ticks;
+        ticks
+    }
+
+    /// How far into the next tick, 0.0..1.0.
+    pub fn alpha(&self) -> f32 {
+        self.time / self.tick_len
     }
 
     pub fn update_from_tick_settings(&mut self, tick_settings: &TickSettings) -> &mut Self {
-        self.start_time = tick_settings.tick_len_secs;
+        // keep alpha the same so drawing doesn't jump
+        let alpha = self.alpha();
+        self.tick_len = tick_settings.tick_len_secs;
+        self.time = alpha * self.tick_len;
         self
     }
 }
 
+impl Default for Accumulator {
+    fn default() -> Self {
+        Self::new(TickSettings::default().tick_len_secs)
+    }
+}
+
+/// Seconds since a fixed point in time, the same on every thread that has a copy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Clock {
+    start: Instant,
+}
+
+impl Clock {
+    /// A clock that reads `secs` right now.
+    pub fn starting_at(secs: f64) -> Self {
+        let now = Instant::now();
+        let start = now.checked_sub(Duration::from_secs_f64(secs.max(0.0))).unwrap_or(now);
+        Self { start }
+    }
+
+    pub fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64()
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::starting_at(0.0)
+    }
+}
+
 /// Timings of the game loop
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Timings {
@@ -69,3 +137,29 @@ impl Timings {
         Self::duration_since_opt(self.waiting, self.start).unwrap_or_default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulator() {
+        let mut acc = Accumulator::new(0.1);
+        assert_eq!(acc.add(0.05), 0);
+        assert!((acc.alpha() - 0.5).abs() < 1e-5);
+        assert_eq!(acc.add(0.3), 3);
+        assert!((acc.alpha() - 0.5).abs() < 1e-5);
+
+        // 1000 frames at 1000 fps is 10 ticks
+        let ticks = (0..1000).map(|_| acc.add(0.001)).sum::<usize>();
+        assert_eq!(ticks, 10);
+    }
+
+    #[test]
+    fn spiral_of_death() {
+        let mut acc = Accumulator::new(0.01);
+        assert_eq!(acc.add(1.0), Accumulator::DEFAULT_MAX_TICKS_PER_FRAME);
+        assert_eq!(acc.dropped_ticks, 100 - Accumulator::DEFAULT_MAX_TICKS_PER_FRAME);
+        assert!(acc.alpha() < 1.0);
+    }
+}
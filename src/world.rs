@@ -0,0 +1,249 @@
+//! Static level geometry. Never changes during a game, so it lives next to the ticks
+//! instead of being copied into every one of them.
+
+use macroquad::prelude::*;
+
+/// Axis aligned box, `min` is the top left corner.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Aabb {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Aabb {
+    pub fn new(center: Vec2, size: Vec2) -> Self {
+        Self { min: center - size / 2.0, max: center + size / 2.0 }
+    }
+
+    /// The 4 edges, clockwise.
+    pub fn edges(&self) -> [Segment; 4] {
+        let (min, max) = (self.min, self.max);
+        let top_right = Vec2::new(max.x, min.y);
+        let bottom_left = Vec2::new(min.x, max.y);
+        [
+            Segment::new(min, top_right),
+            Segment::new(top_right, max),
+            Segment::new(max, bottom_left),
+            Segment::new(bottom_left, min),
+        ]
+    }
+}
+
+/// Wall with no thickness, solid from both sides.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Segment {
+    pub a: Vec2,
+    pub b: Vec2,
+}
+
+impl Segment {
+    pub fn new(a: Vec2, b: Vec2) -> Self {
+        Self { a, b }
+    }
+
+    /// When a circle of `radius` moving from `pos` by `delta` first touches this, as a
+    /// fraction of `delta`, and the direction to push it out.
+    pub fn sweep(&self, pos: Vec2, delta: Vec2, radius: f32) -> Option<Contact> {
+        let along = self.b - self.a;
+        let side = along.perp().try_normalize();
+        // the flat side, only if the contact point lands between the ends
+        let flat = side.and_then(|side| {
+            let dist = (pos - self.a).dot(side);
+            let normal = if dist < 0.0 { -side } else { side };
+            let approach = -delta.dot(normal);
+            if approach <= 0.0 {
+                return None;
+            }
+            // already overlapping gets pushed out right away
+            let t = ((dist.abs() - radius) / approach).max(0.0);
+            if t > 1.0 {
+                return None;
+            }
+            let touch = pos + delta * t - normal * radius;
+            let along_t = (touch - self.a).dot(along) / along.length_squared();
+            (0.0..=1.0).contains(&along_t).then_some(Contact { t, normal })
+        });
+        let ends = [self.a, self.b].map(|end| sweep_point(pos, delta, radius, end));
+        [flat, ends[0], ends[1]]
+            .into_iter()
+            .flatten()
+            .min_by(|a, b| a.t.total_cmp(&b.t))
+    }
+}
+
+/// Circle vs point, like the round ends of a wall.
+fn sweep_point(pos: Vec2, delta: Vec2, radius: f32, point: Vec2) -> Option<Contact> {
+    let offset = pos - point;
+    let a = delta.length_squared();
+    let b = 2.0 * delta.dot(offset);
+    let c = offset.length_squared() - radius * radius;
+    if a == 0.0 || b >= 0.0 {
+        // not moving, or moving away
+        return None;
+    }
+    let t = if c <= 0.0 {
+        0.0
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        (-b - discriminant.sqrt()) / (2.0 * a)
+    };
+    let normal = (offset + delta * t).try_normalize()?;
+    (t <= 1.0).then_some(Contact { t, normal })
+}
+
+/// Where a sweep hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    /// Fraction of the movement until touching.
+    pub t: f32,
+    /// Away from the wall.
+    pub normal: Vec2,
+}
+
+impl Contact {
+    /// Gap kept to walls, so float error doesn't end up inside them.
+    const SKIN: f32 = 1e-3;
+}
+
+/// Everything solid in a level.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct World {
+    /// Where `boxes` and `segments` are relative to, follows spawn in a
+    /// [`Simulation`](crate::state::Simulation).
+    pub origin: Vec2,
+    pub boxes: Vec<Aabb>,
+    pub segments: Vec<Segment>,
+}
+
+impl World {
+    /// Nothing to bump into.
+    pub const EMPTY: Self = Self { origin: Vec2::ZERO, boxes: Vec::new(), segments: Vec::new() };
+    /// Most times to slide along a wall per move, corners take 2.
+    const MAX_SLIDES: usize = 4;
+    const COLOR: Color = DARKGRAY;
+
+    /// The size of the default window around `origin` with a few things in it.
+    /// Leaves room for NPCs circling spawn.
+    pub fn arena() -> Self {
+        let walls = Aabb::new(Vec2::ZERO, Vec2::new(780.0, 580.0));
+        let mut segments = walls.edges().to_vec();
+        segments.push(Segment::new(Vec2::new(230.0, -100.0), Vec2::new(330.0, -200.0)));
+        let boxes = vec![
+            Aabb::new(Vec2::new(-280.0, -190.0), Vec2::new(80.0, 60.0)),
+            Aabb::new(Vec2::new(-260.0, 190.0), Vec2::new(40.0, 120.0)),
+            Aabb::new(Vec2::new(270.0, 200.0), Vec2::new(100.0, 40.0)),
+        ];
+        Self { origin: Vec2::ZERO, boxes, segments }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.boxes.is_empty() && self.segments.is_empty()
+    }
+
+    /// In world space, moved by `origin`.
+    fn all_segments(&self) -> impl Iterator<Item = Segment> + '_ {
+        let origin = self.origin;
+        self.segments
+            .iter()
+            .copied()
+            .chain(self.boxes.iter().flat_map(|aabb| aabb.edges()))
+            .map(move |segment| Segment::new(segment.a + origin, segment.b + origin))
+    }
+
+    /// First thing a circle of `radius` moving from `pos` by `delta` touches.
+    pub fn sweep(&self, pos: Vec2, delta: Vec2, radius: f32) -> Option<Contact> {
+        self.all_segments()
+            .filter_map(|segment| segment.sweep(pos, delta, radius))
+            .min_by(|a, b| a.t.total_cmp(&b.t))
+    }
+
+    /// Move a circle by `delta`, sliding along whatever it hits. Returns where it ended up.
+    /// `on_hit` gets the normal of every wall hit, to stop velocity going into it.
+    pub fn slide(
+        &self, mut pos: Vec2, mut delta: Vec2, radius: f32, mut on_hit: impl FnMut(Vec2),
+    ) -> Vec2 {
+        for _ in 0..Self::MAX_SLIDES {
+            let Some(contact) = self.sweep(pos, delta, radius) else {
+                return pos + delta;
+            };
+            pos += delta * contact.t + contact.normal * Contact::SKIN;
+            delta *= 1.0 - contact.t;
+            delta -= contact.normal * delta.dot(contact.normal).min(0.0);
+            on_hit(contact.normal);
+        }
+        // wedged in a corner
+        pos
+    }
+
+    /// Where a circle drawn at `to` should really be drawn if it was at `from` before, so
+    /// curvy interpolation doesn't cut through walls between ticks.
+    pub fn constrain(&self, from: Vec2, to: Vec2, radius: f32) -> Vec2 {
+        if self.is_empty() {
+            return to;
+        }
+        self.slide(from, to - from, radius, |_| {})
+    }
+
+    pub fn draw(&self) {
+        for aabb in self.boxes.iter() {
+            let (min, size) = (aabb.min + self.origin, aabb.max - aabb.min);
+            draw_rectangle(min.x, min.y, size.x, size.y, Self::COLOR);
+        }
+        for segment in self.segments.iter() {
+            let (a, b) = (segment.a + self.origin, segment.b + self.origin);
+            draw_line(a.x, a.y, b.x, b.y, 4.0, Self::COLOR);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep() {
+        let wall = Segment::new(Vec2::new(10.0, -10.0), Vec2::new(10.0, 10.0));
+        // straight into the flat side
+        let contact = wall.sweep(Vec2::ZERO, Vec2::new(20.0, 0.0), 2.0).unwrap();
+        assert!((contact.t - 0.4).abs() < 1e-6);
+        assert_eq!(contact.normal, -Vec2::X);
+        // from the other side, too short, moving away, past the end
+        assert_eq!(wall.sweep(Vec2::new(20.0, 0.0), -Vec2::X * 20.0, 2.0).unwrap().normal, Vec2::X);
+        assert!(wall.sweep(Vec2::ZERO, Vec2::new(5.0, 0.0), 2.0).is_none());
+        assert!(wall.sweep(Vec2::ZERO, Vec2::new(-20.0, 0.0), 2.0).is_none());
+        assert!(wall.sweep(Vec2::new(0.0, 13.0), Vec2::new(20.0, 0.0), 2.0).is_none());
+        // clips the round end
+        let contact = wall.sweep(Vec2::new(0.0, 11.0), Vec2::new(20.0, 0.0), 2.0).unwrap();
+        assert!(contact.normal.y > 0.0 && contact.normal.x < 0.0);
+    }
+
+    #[test]
+    fn slide() {
+        let world = World {
+            boxes: vec![Aabb::new(Vec2::new(0.0, 20.0), Vec2::new(100.0, 20.0))],
+            ..World::EMPTY
+        };
+        // diagonally into the floor, keeps going sideways on top of it
+        let mut normals = Vec::new();
+        let pos = world.slide(Vec2::ZERO, Vec2::new(10.0, 20.0), 5.0, |n| normals.push(n));
+        assert_eq!(normals, [-Vec2::Y]);
+        assert!((pos - Vec2::new(10.0, 5.0)).abs().max_element() < 1e-2, "{}", pos);
+
+        // fast enough to tunnel through if it wasn't swept
+        let pos = world.slide(Vec2::ZERO, Vec2::new(0.0, 1000.0), 5.0, |_| {});
+        assert!(pos.y < 5.0, "{}", pos);
+
+        // a curve that bulges into the box gets pushed back out
+        let pos = world.constrain(Vec2::ZERO, Vec2::new(0.0, 15.0), 5.0);
+        assert!(pos.y < 5.0, "{}", pos);
+        assert_eq!(World::EMPTY.constrain(Vec2::ZERO, Vec2::Y * 15.0, 5.0), Vec2::Y * 15.0);
+
+        // moving the origin moves everything
+        let moved = World { origin: Vec2::new(0.0, 100.0), ..world };
+        assert!(moved.sweep(Vec2::ZERO, Vec2::new(0.0, 20.0), 5.0).is_none());
+        assert!(moved.sweep(Vec2::new(0.0, 100.0), Vec2::new(0.0, 20.0), 5.0).is_some());
+    }
+}